@@ -0,0 +1,80 @@
+use dale::vm::{INITIAL_PC, VM};
+use std::time::{Duration, Instant};
+
+const CYCLES: usize = 10_000_000;
+
+// A hot arithmetic loop drawing a glyph on every iteration
+const DRAW_LOOP: [u16; 12] = [
+    0x6000, // 0x200: LD V0, 0x00
+    0x6101, // 0x202: LD V1, 0x01
+    0x8014, // 0x204: ADD V0, V1
+    0x8203, // 0x206: XOR V2, V0
+    0x8306, // 0x208: SHR V3
+    0x8621, // 0x20a: OR V6, V2
+    0xf729, // 0x20c: LD F, V7
+    0xd015, // 0x20e: DRW V0, V1, 5
+    0x3000, // 0x210: SE V0, 0x00
+    0x1204, // 0x212: JP 0x204
+    0x8414, // 0x214: ADD V4, V1
+    0x1204, // 0x216: JP 0x204
+];
+
+// A busy-wait counting loop of skips and jumps, where per-instruction overhead dominates
+const SKIP_LOOP: [u16; 5] = [
+    0x7001, // 0x200: ADD V0, 0x01
+    0x3000, // 0x202: SE V0, 0x00
+    0x1200, // 0x204: JP 0x200
+    0x7101, // 0x206: ADD V1, 0x01
+    0x1200, // 0x208: JP 0x200
+];
+
+fn load(program: &[u16]) -> VM {
+    let mut vm = VM::new();
+    vm.write_program(INITIAL_PC as usize, program).unwrap();
+
+    vm
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{}: {} cycles in {:?} ({:.2} Mcycles/s)",
+        name,
+        CYCLES,
+        elapsed,
+        CYCLES as f64 / elapsed.as_secs_f64() / 1_000_000.0
+    );
+}
+
+fn bench(name: &str, program: &[u16]) {
+    let mut vm = load(program);
+    let start = Instant::now();
+
+    for _ in 0..CYCLES {
+        vm.step();
+    }
+
+    report(&format!("{}, step", name), start.elapsed());
+
+    let mut vm = load(program);
+    vm.set_decode_cache_enabled(true);
+    let start = Instant::now();
+
+    for _ in 0..CYCLES {
+        vm.step();
+    }
+
+    report(&format!("{}, step, decode cache", name), start.elapsed());
+
+    let mut vm = load(program);
+    vm.set_decode_cache_enabled(true);
+    let start = Instant::now();
+
+    vm.run_n(CYCLES);
+
+    report(&format!("{}, run_n, decode cache", name), start.elapsed());
+}
+
+fn main() {
+    bench("draw loop", &DRAW_LOOP);
+    bench("skip loop", &SKIP_LOOP);
+}
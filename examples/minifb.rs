@@ -0,0 +1,99 @@
+use dale::clock::Clock;
+use dale::display::{LORES_HEIGHT, LORES_WIDTH};
+use dale::vm::VM;
+use minifb::{Key, Window, WindowOptions};
+
+const SCALE: usize = 10;
+const WINDOW_WIDTH: usize = LORES_WIDTH * SCALE;
+const WINDOW_HEIGHT: usize = LORES_HEIGHT * SCALE;
+const CYCLES_PER_SECOND: u32 = 600;
+const ON_COLOR: [u8; 4] = [0xff, 0xcc, 0x00, 0xff];
+const OFF_COLOR: [u8; 4] = [0x99, 0x66, 0x00, 0xff];
+
+// 1 2 3 4     1 2 3 C
+// Q W E R  -> 4 5 6 D
+// A S D F     7 8 9 E
+// Z X C V     A 0 B F
+const KEYMAP: [(Key, u8); 16] = [
+    (Key::Key1, 0x1),
+    (Key::Key2, 0x2),
+    (Key::Key3, 0x3),
+    (Key::Key4, 0xc),
+    (Key::Q, 0x4),
+    (Key::W, 0x5),
+    (Key::E, 0x6),
+    (Key::R, 0xd),
+    (Key::A, 0x7),
+    (Key::S, 0x8),
+    (Key::D, 0x9),
+    (Key::F, 0xe),
+    (Key::Z, 0xa),
+    (Key::X, 0x0),
+    (Key::C, 0xb),
+    (Key::V, 0xf),
+];
+
+// Scales the RGBA export to the window, hires frames get half the scale of lores ones
+fn blit(vm: &VM, buffer: &mut [u32]) {
+    let width = vm.display.width();
+    let height = vm.display.height();
+    let rgba = vm.display.to_rgba(ON_COLOR, OFF_COLOR);
+
+    for (i, pixel) in buffer.iter_mut().enumerate() {
+        let x = (i % WINDOW_WIDTH) * width / WINDOW_WIDTH;
+        let y = (i / WINDOW_WIDTH) * height / WINDOW_HEIGHT;
+        let offset = (y * width + x) * 4;
+        let (r, g, b) = (rgba[offset], rgba[offset + 1], rgba[offset + 2]);
+
+        *pixel = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+    }
+}
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: minifb <rom>");
+            std::process::exit(1);
+        }
+    };
+
+    let mut vm = VM::new();
+    vm.load_rom(&path);
+
+    let mut window = Window::new(
+        "dale",
+        WINDOW_WIDTH,
+        WINDOW_HEIGHT,
+        WindowOptions::default(),
+    )
+    .unwrap_or_else(|error| panic!("{}", error));
+    window.limit_update_rate(Some(std::time::Duration::from_micros(16_667)));
+
+    let mut buffer = vec![0u32; WINDOW_WIDTH * WINDOW_HEIGHT];
+    let mut clock = Clock::new(CYCLES_PER_SECOND);
+    let mut last = std::time::Instant::now();
+
+    while window.is_open() && !window.is_key_down(Key::Escape) && !vm.is_halted() {
+        for (key, chip8_key) in KEYMAP.iter() {
+            if window.is_key_down(*key) {
+                vm.keypad.key_down(*chip8_key);
+            } else {
+                vm.keypad.key_up(*chip8_key);
+            }
+        }
+
+        let now = std::time::Instant::now();
+        vm.run_frame(clock.cycles_for_elapsed(now - last));
+        last = now;
+
+        if vm.display.is_dirty() {
+            blit(&vm, &mut buffer);
+            vm.display.clear_dirty();
+        }
+
+        window
+            .update_with_buffer(&buffer, WINDOW_WIDTH, WINDOW_HEIGHT)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+}
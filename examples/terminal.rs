@@ -0,0 +1,114 @@
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, terminal};
+use dale::clock::Clock;
+use dale::keypad::Keypad;
+use dale::renderer::{AsciiRenderer, Renderer};
+use dale::vm::VM;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const CYCLES_PER_SECOND: u32 = 600;
+// Most terminals only report presses, keys are released after this many frames
+const KEY_HOLD_FRAMES: u8 = 6;
+
+fn run(vm: &mut VM) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let mut renderer = AsciiRenderer::new();
+    let mut clock = Clock::new(CYCLES_PER_SECOND);
+    let mut held = [0u8; 16];
+    let mut last = Instant::now();
+
+    loop {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            }) = event::read()?
+            {
+                match code {
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(())
+                    }
+                    KeyCode::Char(c) => {
+                        if let Some(key) = Keypad::from_qwerty(c) {
+                            if kind == KeyEventKind::Release {
+                                held[key as usize] = 0;
+                                vm.keypad.key_up(key);
+                            } else {
+                                held[key as usize] = KEY_HOLD_FRAMES;
+                                vm.keypad.key_down(key);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let cycles = clock.cycles_for_elapsed(now - last);
+        last = now;
+
+        vm.run_frame(cycles);
+
+        for (key, frames) in held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    vm.keypad.key_up(key as u8);
+                }
+            }
+        }
+
+        if vm.display.is_dirty() {
+            renderer.draw(&vm.display);
+            vm.display.clear_dirty();
+
+            queue!(stdout, cursor::MoveTo(0, 0))?;
+            for line in renderer.frame.lines() {
+                write!(stdout, "{}\r\n", line)?;
+            }
+            stdout.flush()?;
+        }
+
+        if vm.is_halted() {
+            return Ok(());
+        }
+
+        std::thread::sleep(FRAME.saturating_sub(now.elapsed()));
+    }
+}
+
+fn main() -> io::Result<()> {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: terminal <rom>");
+            std::process::exit(1);
+        }
+    };
+
+    let mut vm = VM::new();
+    vm.load_rom(&path);
+
+    let mut stdout = io::stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(
+        stdout,
+        terminal::EnterAlternateScreen,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::Hide
+    )?;
+
+    let result = run(&mut vm);
+
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+
+    result
+}
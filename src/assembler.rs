@@ -0,0 +1,439 @@
+use crate::instruction::Instruction;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+// Address of the first assembled byte, labels resolve relative to it
+pub const ORIGIN: u16 = 0x200;
+
+// Line numbers start at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AssembleError {
+    UnknownMnemonic { line: usize, mnemonic: String },
+    InvalidOperands { line: usize },
+    ValueOutOfRange { line: usize, value: u32 },
+    UnknownLabel { line: usize, label: String },
+    DuplicateLabel { line: usize, label: String },
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "Line {}: unknown mnemonic {}", line, mnemonic)
+            }
+            AssembleError::InvalidOperands { line } => write!(f, "Line {}: invalid operands", line),
+            AssembleError::ValueOutOfRange { line, value } => {
+                write!(f, "Line {}: value {:#x} out of range", line, value)
+            }
+            AssembleError::UnknownLabel { line, label } => {
+                write!(f, "Line {}: unknown label {}", line, label)
+            }
+            AssembleError::DuplicateLabel { line, label } => {
+                write!(f, "Line {}: label {} is already defined", line, label)
+            }
+        }
+    }
+}
+
+// Mnemonic in upper case and its comma separated operands
+type Statement<'a> = (String, Vec<&'a str>);
+
+// Operand as written in the source, addresses may still be labels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand<'a> {
+    V(u8),
+    Value(u32),
+    Label(&'a str),
+    Keyword(&'a str),
+}
+
+// Assembles the mnemonics printed by Instruction's Display, e.g. "LD V1, 0x23", with
+// `label:` definitions, `;` comments and DB/DW data. The first pass only sizes every line
+// to assign the labels, the second one encodes it
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut labels = BTreeMap::new();
+    let mut address = ORIGIN as u32;
+
+    for (line, text) in lines(source) {
+        let (label, statement) = split_label(text);
+
+        if let Some(label) = label {
+            if labels.insert(label, address).is_some() {
+                return Err(AssembleError::DuplicateLabel {
+                    line,
+                    label: label.to_string(),
+                });
+            }
+        }
+
+        if let Some((mnemonic, operands)) = statement {
+            address += size(&mnemonic, &operands) as u32;
+        }
+    }
+
+    let mut bytes = Vec::new();
+
+    for (line, text) in lines(source) {
+        if let (_, Some((mnemonic, operands))) = split_label(text) {
+            encode(line, &mnemonic, &operands, &labels, &mut bytes)?;
+        }
+    }
+
+    Ok(bytes)
+}
+
+// Numbered lines with the comments stripped
+fn lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    source.lines().enumerate().map(|(index, text)| {
+        let text = text.split(';').next().unwrap_or("");
+
+        (index + 1, text.trim())
+    })
+}
+
+// "loop: JP loop" -> (Some("loop"), Some(("JP", ["loop"])))
+fn split_label(text: &str) -> (Option<&str>, Option<Statement<'_>>) {
+    let (label, rest) = match text.find(':') {
+        Some(index) => (Some(text[..index].trim()), text[index + 1..].trim()),
+        None => (None, text),
+    };
+
+    if rest.is_empty() {
+        return (label, None);
+    }
+
+    let (mnemonic, operands) = match rest.find(char::is_whitespace) {
+        Some(index) => (&rest[..index], rest[index..].trim()),
+        None => (rest, ""),
+    };
+    let operands = if operands.is_empty() {
+        Vec::new()
+    } else {
+        operands.split(',').map(str::trim).collect()
+    };
+
+    (label, Some((mnemonic.to_ascii_uppercase(), operands)))
+}
+
+fn size(mnemonic: &str, operands: &[&str]) -> usize {
+    match mnemonic {
+        "DB" => operands.len(),
+        "DW" => operands.len() * 2,
+        "LD" if is_long(operands) => 4,
+        _ => 2,
+    }
+}
+
+// LD I, long addr
+fn is_long(operands: &[&str]) -> bool {
+    let mut words = operands
+        .get(1)
+        .into_iter()
+        .flat_map(|operand| operand.split_whitespace());
+
+    operands.len() == 2
+        && operands[0].eq_ignore_ascii_case("I")
+        && words
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case("long"))
+        && words.next().is_some()
+}
+
+fn parse_operand(text: &str) -> Option<Operand<'_>> {
+    let lower = text.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        None
+    };
+
+    if let Some(value) = value {
+        return Some(Operand::Value(value));
+    }
+
+    if lower.len() == 2 && lower.starts_with('v') {
+        if let Ok(x) = u8::from_str_radix(&lower[1..], 16) {
+            return Some(Operand::V(x));
+        }
+    }
+
+    match lower.as_str() {
+        "i" | "dt" | "st" | "k" | "f" | "hf" | "b" | "[i]" | "r" => Some(Operand::Keyword(text)),
+        _ if text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            Some(Operand::Label(text))
+        }
+        _ => None,
+    }
+}
+
+fn encode(
+    line: usize,
+    mnemonic: &str,
+    operands: &[&str],
+    labels: &BTreeMap<&str, u32>,
+    bytes: &mut Vec<u8>,
+) -> Result<(), AssembleError> {
+    use Operand::V;
+
+    let invalid = AssembleError::InvalidOperands { line };
+
+    if is_long(operands) && mnemonic == "LD" {
+        let address = resolve(line, operands[1][4..].trim(), labels, 0xffff)?;
+
+        bytes.extend_from_slice(&Instruction::LdILong.encode().to_be_bytes());
+        bytes.extend_from_slice(&(address as u16).to_be_bytes());
+
+        return Ok(());
+    }
+
+    if mnemonic == "DB" || mnemonic == "DW" {
+        let max = if mnemonic == "DB" { 0xff } else { 0xffff };
+
+        for operand in operands {
+            let value = resolve(line, operand, labels, max)?;
+
+            if mnemonic == "DB" {
+                bytes.push(value as u8);
+            } else {
+                bytes.extend_from_slice(&(value as u16).to_be_bytes());
+            }
+        }
+
+        return Ok(());
+    }
+
+    let parsed = operands
+        .iter()
+        .map(|operand| parse_operand(operand))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| invalid.clone())?;
+
+    let keyword = |operand: &Operand, name: &str| match operand {
+        Operand::Keyword(keyword) => keyword.eq_ignore_ascii_case(name),
+        _ => false,
+    };
+    let address = |index: usize| resolve(line, operands[index], labels, 0xfff).map(|a| a as u16);
+    let byte = |index: usize| resolve_value(line, &parsed[index], 0xff).map(|b| b as u8);
+    let nibble = |index: usize| resolve_value(line, &parsed[index], 0xf).map(|n| n as u8);
+
+    let instruction = match (mnemonic, parsed.as_slice()) {
+        ("CLS", []) => Instruction::Cls,
+        ("RET", []) => Instruction::Ret,
+        ("SCR", []) => Instruction::Scr,
+        ("SCL", []) => Instruction::Scl,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::Low,
+        ("HIGH", []) => Instruction::High,
+        ("AUDIO", []) => Instruction::Audio,
+        ("SCD", [_]) => Instruction::Scd(nibble(0)?),
+        ("SCU", [_]) => Instruction::Scu(nibble(0)?),
+        ("PLANE", [_]) => Instruction::Plane(nibble(0)?),
+        ("SYS", [_]) => Instruction::Sys(address(0)?),
+        ("JP", [V(0), _]) => Instruction::JpV0(address(1)?),
+        ("JP", [_]) => Instruction::Jp(address(0)?),
+        ("CALL", [_]) => Instruction::Call(address(0)?),
+        ("SE", [V(x), V(y)]) => Instruction::SeReg(*x, *y),
+        ("SE", [V(x), _]) => Instruction::SeByte(*x, byte(1)?),
+        ("SNE", [V(x), V(y)]) => Instruction::SneReg(*x, *y),
+        ("SNE", [V(x), _]) => Instruction::SneByte(*x, byte(1)?),
+        ("LD", [V(x), V(y)]) => Instruction::LdReg(*x, *y),
+        ("LD", [V(x), k]) if keyword(k, "DT") => Instruction::LdVxDt(*x),
+        ("LD", [V(x), k]) if keyword(k, "K") => Instruction::LdVxK(*x),
+        ("LD", [V(x), k]) if keyword(k, "[I]") => Instruction::LdVxI(*x),
+        ("LD", [V(x), k]) if keyword(k, "R") => Instruction::LdVxR(*x),
+        ("LD", [V(x), _]) => Instruction::LdByte(*x, byte(1)?),
+        ("LD", [k, _]) if keyword(k, "I") => Instruction::LdI(address(1)?),
+        ("LD", [k, V(x)]) if keyword(k, "DT") => Instruction::LdDtVx(*x),
+        ("LD", [k, V(x)]) if keyword(k, "ST") => Instruction::LdStVx(*x),
+        ("LD", [k, V(x)]) if keyword(k, "F") => Instruction::LdF(*x),
+        ("LD", [k, V(x)]) if keyword(k, "HF") => Instruction::LdHf(*x),
+        ("LD", [k, V(x)]) if keyword(k, "B") => Instruction::LdB(*x),
+        ("LD", [k, V(x)]) if keyword(k, "[I]") => Instruction::LdIVx(*x),
+        ("LD", [k, V(x)]) if keyword(k, "R") => Instruction::LdRVx(*x),
+        ("ADD", [V(x), V(y)]) => Instruction::AddReg(*x, *y),
+        ("ADD", [V(x), _]) => Instruction::AddByte(*x, byte(1)?),
+        ("ADD", [k, V(x)]) if keyword(k, "I") => Instruction::AddI(*x),
+        ("OR", [V(x), V(y)]) => Instruction::Or(*x, *y),
+        ("AND", [V(x), V(y)]) => Instruction::And(*x, *y),
+        ("XOR", [V(x), V(y)]) => Instruction::Xor(*x, *y),
+        ("SUB", [V(x), V(y)]) => Instruction::Sub(*x, *y),
+        ("SUBN", [V(x), V(y)]) => Instruction::Subn(*x, *y),
+        ("SHR", [V(x), V(y)]) => Instruction::Shr(*x, *y),
+        ("SHR", [V(x)]) => Instruction::Shr(*x, *x),
+        ("SHL", [V(x), V(y)]) => Instruction::Shl(*x, *y),
+        ("SHL", [V(x)]) => Instruction::Shl(*x, *x),
+        ("RND", [V(x), _]) => Instruction::Rnd(*x, byte(1)?),
+        ("DRW", [V(x), V(y), _]) => Instruction::Drw(*x, *y, nibble(2)?),
+        ("SKP", [V(x)]) => Instruction::Skp(*x),
+        ("SKNP", [V(x)]) => Instruction::Sknp(*x),
+        ("PITCH", [V(x)]) => Instruction::Pitch(*x),
+        (
+            "CLS" | "RET" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "AUDIO" | "SCD" | "SCU"
+            | "PLANE" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND" | "XOR"
+            | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "PITCH",
+            _,
+        ) => return Err(invalid),
+        _ => {
+            return Err(AssembleError::UnknownMnemonic {
+                line,
+                mnemonic: mnemonic.to_string(),
+            })
+        }
+    };
+
+    bytes.extend_from_slice(&instruction.encode().to_be_bytes());
+
+    Ok(())
+}
+
+fn resolve(
+    line: usize,
+    text: &str,
+    labels: &BTreeMap<&str, u32>,
+    max: u32,
+) -> Result<u32, AssembleError> {
+    match parse_operand(text) {
+        Some(Operand::Label(label)) => match labels.get(label) {
+            Some(&address) => resolve_value(line, &Operand::Value(address), max),
+            None => Err(AssembleError::UnknownLabel {
+                line,
+                label: label.to_string(),
+            }),
+        },
+        Some(operand) => resolve_value(line, &operand, max),
+        None => Err(AssembleError::InvalidOperands { line }),
+    }
+}
+
+fn resolve_value(line: usize, operand: &Operand, max: u32) -> Result<u32, AssembleError> {
+    match *operand {
+        Operand::Value(value) if value > max => Err(AssembleError::ValueOutOfRange { line, value }),
+        Operand::Value(value) => Ok(value),
+        _ => Err(AssembleError::InvalidOperands { line }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler;
+
+    #[test]
+    fn assemble_mnemonics() {
+        let source = "
+            CLS
+            LD V1, 0x23      ; comment
+            ld v2, v1
+            LD I, 0x300
+            LD I, long 0x1234
+            DRW V0, V1, 5
+            SHR V3
+            LD [I], VF
+            LD VA, [I]
+            JP V0, 0x208
+            DB 0xff, 1
+            DW 0xabcd
+        ";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                0x00, 0xe0, 0x61, 0x23, 0x82, 0x10, 0xa3, 0x00, 0xf0, 0x00, 0x12, 0x34, 0xd0, 0x15,
+                0x83, 0x36, 0xff, 0x55, 0xfa, 0x65, 0xb2, 0x08, 0xff, 0x01, 0xab, 0xcd,
+            ])
+        );
+    }
+
+    #[test]
+    fn assemble_disassembly() {
+        let mut memory = Vec::new();
+
+        for opcode in (0..=0xffffu16).step_by(7) {
+            if let Ok(instruction) = Instruction::decode(opcode) {
+                // LD I, long would take the next opcode as its operand
+                if instruction != Instruction::LdILong {
+                    memory.extend_from_slice(&opcode.to_be_bytes());
+                }
+            }
+        }
+
+        let source: Vec<String> = disassembler::disassemble(&memory, 0, memory.len() as u16)
+            .into_iter()
+            .map(|(_, _, mnemonic)| mnemonic)
+            .collect();
+
+        assert_eq!(assemble(&source.join("\n")), Ok(memory));
+    }
+
+    #[test]
+    fn assemble_labels() {
+        let source = "
+            start:
+                LD V0, 0
+            loop:
+                ADD V0, 1
+                SE V0, 10
+                JP loop
+                CALL done
+                JP start
+            done: RET
+        ";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                0x60, 0x00, // 0x200: LD V0, 0x00
+                0x70, 0x01, // 0x202: ADD V0, 0x01
+                0x30, 0x0a, // 0x204: SE V0, 0x0a
+                0x12, 0x02, // 0x206: JP 0x202
+                0x22, 0x0c, // 0x208: CALL 0x20c
+                0x12, 0x00, // 0x20a: JP 0x200
+                0x00, 0xee, // 0x20c: RET
+            ])
+        );
+    }
+
+    #[test]
+    fn assemble_errors() {
+        assert_eq!(
+            assemble("JP nowhere"),
+            Err(AssembleError::UnknownLabel {
+                line: 1,
+                label: "nowhere".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("a:\na:"),
+            Err(AssembleError::DuplicateLabel {
+                line: 2,
+                label: "a".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("NOP"),
+            Err(AssembleError::UnknownMnemonic {
+                line: 1,
+                mnemonic: "NOP".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("\nLD V0, 0x100"),
+            Err(AssembleError::ValueOutOfRange {
+                line: 2,
+                value: 0x100
+            })
+        );
+        assert_eq!(
+            assemble("OR V0, 1"),
+            Err(AssembleError::InvalidOperands { line: 1 })
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use alloc::vec::Vec;
+
+// Byte addressable memory as seen by the CPU, addresses are already wrapped to the VM memory
+// size, implementations can map devices anywhere in that range
+pub trait Bus {
+    fn read(&self, address: usize) -> u8;
+    fn write(&mut self, address: usize, value: u8);
+}
+
+// The flat RAM every VM starts with
+impl Bus for Vec<u8> {
+    fn read(&self, address: usize) -> u8 {
+        self[address]
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        self[address] = value;
+    }
+}
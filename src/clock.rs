@@ -0,0 +1,90 @@
+use core::time::Duration;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+// Turns elapsed host time into a number of VM steps for a target instructions-per-second,
+// carrying the fractional cycle over to the next call
+#[derive(Clone, Debug)]
+pub struct Clock {
+    hz: u32,
+    remainder: u128,
+}
+
+impl Clock {
+    pub fn new(hz: u32) -> Clock {
+        Clock { hz, remainder: 0 }
+    }
+
+    pub fn hz(&self) -> u32 {
+        self.hz
+    }
+
+    pub fn set_hz(&mut self, hz: u32) {
+        self.hz = hz;
+        self.remainder = 0;
+    }
+
+    pub fn reset(&mut self) {
+        self.remainder = 0;
+    }
+
+    pub fn cycles_for_elapsed(&mut self, elapsed: Duration) -> usize {
+        let total = elapsed.as_nanos() * self.hz as u128 + self.remainder;
+
+        self.remainder = total % NANOS_PER_SEC;
+
+        (total / NANOS_PER_SEC) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steady_state() {
+        let mut clock = Clock::new(600);
+
+        assert_eq!(clock.hz(), 600);
+
+        for _ in 0..100 {
+            assert_eq!(clock.cycles_for_elapsed(Duration::from_millis(100)), 60);
+        }
+
+        assert_eq!(clock.cycles_for_elapsed(Duration::from_secs(2)), 1200);
+        assert_eq!(clock.cycles_for_elapsed(Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn fractional_accumulation() {
+        let mut clock = Clock::new(700);
+        let frame = Duration::from_nanos(1_000_000_000 / 60);
+        let mut total = 0;
+
+        for _ in 0..60 {
+            let cycles = clock.cycles_for_elapsed(frame);
+
+            assert!(cycles == 11 || cycles == 12);
+
+            total += cycles;
+        }
+
+        assert_eq!(total, 699);
+
+        let mut clock = Clock::new(1);
+
+        assert_eq!(clock.cycles_for_elapsed(Duration::from_millis(400)), 0);
+        assert_eq!(clock.cycles_for_elapsed(Duration::from_millis(400)), 0);
+        assert_eq!(clock.cycles_for_elapsed(Duration::from_millis(400)), 1);
+
+        clock.set_hz(2);
+
+        assert_eq!(clock.cycles_for_elapsed(Duration::from_millis(400)), 0);
+        assert_eq!(clock.cycles_for_elapsed(Duration::from_millis(100)), 1);
+
+        clock.cycles_for_elapsed(Duration::from_millis(400));
+        clock.reset();
+
+        assert_eq!(clock.cycles_for_elapsed(Duration::from_millis(400)), 0);
+    }
+}
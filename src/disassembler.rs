@@ -0,0 +1,224 @@
+use crate::instruction::Instruction;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Address, raw opcode and mnemonic
+pub type Line = (u16, u16, String);
+
+// Words that don't decode are listed as data, addresses wrap around the end of memory
+pub fn disassemble(memory: &[u8], start: u16, end: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = start as usize;
+
+    while address < end as usize {
+        let opcode = read_u16(memory, address);
+
+        match Instruction::decode(opcode) {
+            // The operand of LD I, long is the next word
+            Ok(Instruction::LdILong) => {
+                let operand = read_u16(memory, address + 2);
+
+                lines.push((
+                    address as u16,
+                    opcode,
+                    format!("LD I, long {:#06x}", operand),
+                ));
+
+                if address + 2 < end as usize {
+                    lines.push(((address + 2) as u16, operand, data(operand)));
+                }
+
+                address += 4;
+                continue;
+            }
+            Ok(instruction) => lines.push((address as u16, opcode, instruction.to_string())),
+            Err(_) => lines.push((address as u16, opcode, data(opcode))),
+        }
+
+        address += 2;
+    }
+
+    lines
+}
+
+// Follows the control flow from entry and lists start up to end, only words reached as
+// instructions are decoded, everything else is data. Jumps through JP V0, addr can't be
+// followed so code only reached that way is listed as data too
+pub fn disassemble_traced(memory: &[u8], entry: u16, start: u16, end: u16) -> Vec<Line> {
+    let code = trace(memory, entry);
+    let mut lines = Vec::new();
+    let mut address = start as usize;
+
+    while address < end as usize {
+        let index = address % memory.len();
+
+        if code[index] {
+            let opcode = read_u16(memory, address);
+            let instruction = Instruction::decode(opcode).unwrap();
+
+            if instruction == Instruction::LdILong {
+                let operand = read_u16(memory, address + 2);
+
+                lines.push((
+                    address as u16,
+                    opcode,
+                    format!("LD I, long {:#06x}", operand),
+                ));
+                address += 4;
+            } else {
+                lines.push((address as u16, opcode, instruction.to_string()));
+                address += 2;
+            }
+        } else if code[(index + 1) % memory.len()] || address + 1 == end as usize {
+            // Realigns on code starting at an odd address
+            let byte = memory[index];
+
+            lines.push((address as u16, byte as u16, format!("DB {:#04x}", byte)));
+            address += 1;
+        } else {
+            let word = read_u16(memory, address);
+
+            lines.push((address as u16, word, data(word)));
+            address += 2;
+        }
+    }
+
+    lines
+}
+
+// Marks every address reachable from entry that starts an instruction
+pub(crate) fn trace(memory: &[u8], entry: u16) -> Vec<bool> {
+    let mut code = vec![false; memory.len()];
+    let mut pending = vec![entry as usize % memory.len()];
+
+    while let Some(address) = pending.pop() {
+        if code[address] {
+            continue;
+        }
+
+        let instruction = match Instruction::decode(read_u16(memory, address)) {
+            Ok(instruction) => instruction,
+            Err(_) => continue,
+        };
+
+        code[address] = true;
+
+        let wrap = |address: usize| address % memory.len();
+        let next = wrap(address + size(instruction));
+
+        match instruction {
+            Instruction::Jp(nnn) => pending.push(wrap(nnn as usize)),
+            Instruction::Call(nnn) => {
+                pending.push(wrap(nnn as usize));
+                pending.push(next);
+            }
+            Instruction::Ret | Instruction::Exit | Instruction::JpV0(_) => {}
+            Instruction::SeByte(..)
+            | Instruction::SneByte(..)
+            | Instruction::SeReg(..)
+            | Instruction::SneReg(..)
+            | Instruction::Skp(_)
+            | Instruction::Sknp(_) => {
+                pending.push(next);
+
+                // Skips step over the whole of a following LD I, long
+                let skipped = Instruction::decode(read_u16(memory, next))
+                    .map(size)
+                    .unwrap_or(2);
+                pending.push(wrap(next + skipped));
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    code
+}
+
+fn size(instruction: Instruction) -> usize {
+    if instruction == Instruction::LdILong {
+        4
+    } else {
+        2
+    }
+}
+
+fn data(word: u16) -> String {
+    format!("DW {:#06x}", word)
+}
+
+fn read_u16(memory: &[u8], address: usize) -> u16 {
+    (memory[address % memory.len()] as u16) << 8 | memory[(address + 1) % memory.len()] as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassemble_mixed() {
+        let memory = [
+            0x60, 0x12, // LD V0, 0x12
+            0xf0, 0x00, 0x12, 0x34, // LD I, long 0x1234
+            0xff, 0xff, // data
+            0x80, 0x1f, // data
+            0x12, 0x00, // JP 0x200
+        ];
+
+        let lines = disassemble(&memory, 0, memory.len() as u16);
+
+        assert_eq!(
+            lines,
+            [
+                (0x0, 0x6012, "LD V0, 0x12".to_string()),
+                (0x2, 0xf000, "LD I, long 0x1234".to_string()),
+                (0x4, 0x1234, "DW 0x1234".to_string()),
+                (0x6, 0xffff, "DW 0xffff".to_string()),
+                (0x8, 0x801f, "DW 0x801f".to_string()),
+                (0xa, 0x1200, "JP 0x200".to_string()),
+            ]
+        );
+
+        assert_eq!(disassemble(&memory, 6, 6), []);
+        assert_eq!(
+            disassemble(&memory, 10, 14)[1],
+            (0xc, 0x6012, "LD V0, 0x12".to_string())
+        );
+    }
+
+    #[test]
+    fn disassemble_traced_data_table() {
+        let mut memory = [0; 0x40];
+
+        memory[0x00..0x0c].copy_from_slice(&[
+            0x20, 0x0c, // CALL 0x00c
+            0x30, 0x00, // SE V0, 0x00
+            0xf0, 0x00, 0x00, 0x20, // LD I, long 0x0020
+            0x10, 0x13, // JP 0x013
+            0x60, 0x01, // LD V0, 0x01, data that happens to decode
+        ]);
+        memory[0x0c..0x0e].copy_from_slice(&[0x00, 0xee]); // RET
+        memory[0x0e..0x13].copy_from_slice(&[0xff, 0x81, 0x81, 0x81, 0xff]); // sprite
+        memory[0x13..0x15].copy_from_slice(&[0x10, 0x13]); // JP 0x013
+
+        let lines = disassemble_traced(&memory, 0x000, 0x000, 0x016);
+
+        assert_eq!(
+            lines,
+            [
+                (0x00, 0x200c, "CALL 0x00c".to_string()),
+                (0x02, 0x3000, "SE V0, 0x00".to_string()),
+                (0x04, 0xf000, "LD I, long 0x0020".to_string()),
+                (0x08, 0x1013, "JP 0x013".to_string()),
+                (0x0a, 0x6001, "DW 0x6001".to_string()),
+                (0x0c, 0x00ee, "RET".to_string()),
+                (0x0e, 0xff81, "DW 0xff81".to_string()),
+                (0x10, 0x8181, "DW 0x8181".to_string()),
+                (0x12, 0xff, "DB 0xff".to_string()),
+                (0x13, 0x1013, "JP 0x013".to_string()),
+                (0x15, 0x00, "DB 0x00".to_string()),
+            ]
+        );
+    }
+}
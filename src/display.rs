@@ -0,0 +1,792 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const MAX_PLANES: usize = 4;
+pub const ALL_PLANES: u8 = (1 << MAX_PLANES) - 1;
+
+// One RGBA color for every combination of lit planes, indexed by the pixel's plane mask
+pub type Palette = [[u8; 4]; 1 << MAX_PLANES];
+
+// Black and white for plane 1, two accents for plane 2 and both, dimmer tints for planes 3 and 4
+pub const DEFAULT_PALETTE: Palette = [
+    [0x00, 0x00, 0x00, 0xff],
+    [0xff, 0xff, 0xff, 0xff],
+    [0xff, 0x66, 0x00, 0xff],
+    [0xff, 0xcc, 0x00, 0xff],
+    [0x00, 0x66, 0xcc, 0xff],
+    [0x99, 0xcc, 0xff, 0xff],
+    [0x99, 0x66, 0x99, 0xff],
+    [0xcc, 0x99, 0x99, 0xff],
+    [0x00, 0x99, 0x33, 0xff],
+    [0x99, 0xff, 0x99, 0xff],
+    [0x99, 0x99, 0x33, 0xff],
+    [0xcc, 0xcc, 0x66, 0xff],
+    [0x00, 0x99, 0x99, 0xff],
+    [0x99, 0xff, 0xff, 0xff],
+    [0x66, 0x99, 0x66, 0xff],
+    [0xcc, 0xcc, 0xcc, 0xff],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Lores,
+    Hires,
+}
+
+// Bounding box of the pixels changed since the last Display::clear_dirty
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Clone)]
+pub struct Display {
+    resolution: Resolution,
+    selected_planes: u8,
+    // Every pixel holds one bit per plane
+    pixels: Vec<u8>,
+    dirty: Option<DirtyRegion>,
+    palette: Palette,
+    // Frames a pixel keeps fading after it was turned off, 0 disables fading
+    persistence: u8,
+    // Frames of fading left for every unlit pixel
+    ages: Vec<u8>,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            resolution: Resolution::Lores,
+            selected_planes: 1,
+            pixels: vec![0; LORES_WIDTH * LORES_HEIGHT],
+            dirty: None,
+            palette: DEFAULT_PALETTE,
+            persistence: 0,
+            ages: vec![0; LORES_WIDTH * LORES_HEIGHT],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.selected_planes = 1;
+        self.set_resolution(Resolution::Lores);
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    // Switching resolution always reallocates the buffer, which clears all planes
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        self.pixels = vec![0; self.width() * self.height()];
+        self.ages = vec![0; self.pixels.len()];
+        self.mark_all_dirty();
+    }
+
+    pub fn width(&self) -> usize {
+        match self.resolution {
+            Resolution::Lores => LORES_WIDTH,
+            Resolution::Hires => HIRES_WIDTH,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self.resolution {
+            Resolution::Lores => LORES_HEIGHT,
+            Resolution::Hires => HIRES_HEIGHT,
+        }
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    // Selects which planes clear, scroll and drawing operations affect
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & ALL_PLANES;
+    }
+
+    pub fn clear(&mut self) {
+        let mask = self.selected_planes;
+
+        for index in 0..self.pixels.len() {
+            let before = self.pixels[index];
+
+            self.pixels[index] &= !mask;
+            self.fade(index, before);
+        }
+
+        self.mark_all_dirty();
+    }
+
+    pub(crate) fn raw_pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub(crate) fn restore_raw(&mut self, resolution: Resolution, planes: u8, pixels: &[u8]) {
+        self.set_resolution(resolution);
+        self.select_planes(planes);
+        self.pixels.copy_from_slice(pixels);
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    pub fn dirty_region(&self) -> Option<DirtyRegion> {
+        self.dirty
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = None;
+    }
+
+    fn mark_dirty(&mut self, x: usize, y: usize) {
+        self.dirty = Some(match self.dirty {
+            Some(region) => {
+                let left = region.x.min(x);
+                let top = region.y.min(y);
+                let right = (region.x + region.width).max(x + 1);
+                let bottom = (region.y + region.height).max(y + 1);
+
+                DirtyRegion {
+                    x: left,
+                    y: top,
+                    width: right - left,
+                    height: bottom - top,
+                }
+            }
+            None => DirtyRegion {
+                x,
+                y,
+                width: 1,
+                height: 1,
+            },
+        });
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty = Some(DirtyRegion {
+            x: 0,
+            y: 0,
+            width: self.width(),
+            height: self.height(),
+        });
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.shift(0, n as isize);
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        self.shift(0, -(n as isize));
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        self.shift(n as isize, 0);
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        self.shift(-(n as isize), 0);
+    }
+
+    // Moves the selected planes by (dx, dy), filling the vacated area with off pixels
+    fn shift(&mut self, dx: isize, dy: isize) {
+        let width = self.width() as isize;
+        let height = self.height() as isize;
+        let mask = self.selected_planes;
+        let source = self.pixels.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let bits = if sx >= 0 && sx < width && sy >= 0 && sy < height {
+                    source[(sy * width + sx) as usize] & mask
+                } else {
+                    0
+                };
+
+                let index = (y * width + x) as usize;
+                let before = self.pixels[index];
+
+                self.pixels[index] = (before & !mask) | bits;
+                self.fade(index, before);
+            }
+        }
+
+        self.mark_all_dirty();
+    }
+
+    // One line per row, lit pixels as a full block and unlit ones as spaces
+    pub fn to_ascii(&self) -> String {
+        self.to_ascii_with('█', ' ')
+    }
+
+    pub fn to_ascii_with(&self, on: char, off: char) -> String {
+        let mut ascii = String::with_capacity((self.width() + 1) * self.height());
+
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                ascii.push(if self.pixel(x, y) { on } else { off });
+            }
+            ascii.push('\n');
+        }
+
+        ascii
+    }
+
+    // width * height RGBA pixels colored by the palette entry of their plane combination, `on`
+    // and `off` stand in for the plane 1 and unlit entries. Fading pixels are blended between
+    // `off` and `on` by how many frames of persistence they have left
+    pub fn to_rgba(&self, on: [u8; 4], off: [u8; 4]) -> Vec<u8> {
+        let mut palette = self.palette;
+        palette[0] = off;
+        palette[1] = on;
+
+        let mut rgba = Vec::with_capacity(self.pixels.len() * 4);
+        let steps = self.persistence as u32 + 1;
+
+        for (&pixel, &age) in self.pixels.iter().zip(self.ages.iter()) {
+            if pixel != 0 {
+                rgba.extend_from_slice(&palette[(pixel & ALL_PLANES) as usize]);
+            } else if age == 0 {
+                rgba.extend_from_slice(&off);
+            } else {
+                for (&on, &off) in on.iter().zip(off.iter()) {
+                    let (on, off) = (on as u32, off as u32);
+                    let blended = (off * (steps - age as u32) + on * age as u32) / steps;
+
+                    rgba.push(blended as u8);
+                }
+            }
+        }
+
+        rgba
+    }
+
+    // Kept across resets like the rest of the host configuration
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = *palette;
+        self.mark_all_dirty();
+    }
+
+    // True when the pixel is lit on any plane
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.planes(x, y) != 0
+    }
+
+    // Coordinates of every pixel lit on any plane, row by row
+    pub fn set_pixels(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        let width = self.width();
+
+        self.pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, &pixel)| pixel != 0)
+            .map(move |(i, _)| ((i % width) as u16, (i / width) as u16))
+    }
+
+    pub fn plane_pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        self.planes(x, y) & (1 << plane) != 0
+    }
+
+    // Bit mask of the planes the pixel is lit on
+    pub fn planes(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width() + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
+        let index = y * self.width() + x;
+        let mask = self.selected_planes;
+        let before = self.pixels[index];
+
+        if value {
+            self.pixels[index] |= mask;
+        } else {
+            self.pixels[index] &= !mask;
+        }
+
+        self.fade(index, before);
+        self.mark_dirty(x, y);
+    }
+
+    // Toggles the pixel on every selected plane, returns true when a lit pixel got erased
+    pub fn xor_pixel(&mut self, x: usize, y: usize) -> bool {
+        let index = y * self.width() + x;
+        let mask = self.selected_planes;
+        let before = self.pixels[index];
+
+        self.pixels[index] ^= mask;
+        self.fade(index, before);
+        self.mark_dirty(x, y);
+        before & mask != 0
+    }
+
+    // Toggles the pixel on a single plane, returns true when a lit pixel got erased
+    pub fn xor_plane_pixel(&mut self, plane: usize, x: usize, y: usize) -> bool {
+        let index = y * self.width() + x;
+        let bit = 1 << plane;
+        let before = self.pixels[index];
+
+        self.pixels[index] ^= bit;
+        self.fade(index, before);
+        self.mark_dirty(x, y);
+        before & bit != 0
+    }
+
+    pub fn persistence(&self) -> u8 {
+        self.persistence
+    }
+
+    // Number of frames a pixel turned off keeps showing dimmed in to_rgba
+    pub fn set_persistence(&mut self, frames: u8) {
+        self.persistence = frames;
+        self.ages.fill(0);
+        self.mark_all_dirty();
+    }
+
+    // Frames of fading left for an unlit pixel, 0 for lit ones and with persistence off
+    pub fn age(&self, x: usize, y: usize) -> u8 {
+        let index = y * self.width() + x;
+
+        if self.pixels[index] != 0 {
+            0
+        } else {
+            self.ages[index]
+        }
+    }
+
+    // Ages every fading pixel by a frame, VM::tick_timers calls it at 60 Hz
+    pub fn tick_persistence(&mut self) {
+        if self.persistence == 0 {
+            return;
+        }
+
+        let mut fading = false;
+
+        for age in self.ages.iter_mut().filter(|age| **age > 0) {
+            *age -= 1;
+            fading = true;
+        }
+
+        if fading {
+            self.mark_all_dirty();
+        }
+    }
+
+    // Starts fading a pixel that just went from lit to unlit
+    fn fade(&mut self, index: usize, before: u8) {
+        if self.persistence > 0 && before != 0 && self.pixels[index] == 0 {
+            self.ages[index] = self.persistence;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initialize() {
+        let display = Display::new();
+        assert_eq!(display.resolution(), Resolution::Lores);
+        assert_eq!(display.width(), LORES_WIDTH);
+        assert_eq!(display.height(), LORES_HEIGHT);
+
+        for y in 0..display.height() {
+            for x in 0..display.width() {
+                assert!(!display.pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn set_resolution() {
+        let mut display = Display::new();
+
+        display.set_pixel(1, 1, true);
+        display.set_resolution(Resolution::Hires);
+
+        assert_eq!(display.resolution(), Resolution::Hires);
+        assert_eq!(display.width(), HIRES_WIDTH);
+        assert_eq!(display.height(), HIRES_HEIGHT);
+        assert!(!display.pixel(1, 1));
+
+        display.set_pixel(HIRES_WIDTH - 1, HIRES_HEIGHT - 1, true);
+        display.set_resolution(Resolution::Lores);
+
+        assert_eq!(display.width(), LORES_WIDTH);
+        assert_eq!(display.height(), LORES_HEIGHT);
+        assert!(!display.pixel(LORES_WIDTH - 1, LORES_HEIGHT - 1));
+    }
+
+    #[test]
+    fn xor_pixel() {
+        let mut display = Display::new();
+
+        assert!(!display.xor_pixel(2, 3));
+        assert!(display.pixel(2, 3));
+
+        assert!(display.xor_pixel(2, 3));
+        assert!(!display.pixel(2, 3));
+    }
+
+    #[test]
+    fn scroll_down() {
+        let mut display = Display::new();
+
+        display.set_pixel(5, 0, true);
+        display.set_pixel(5, LORES_HEIGHT - 2, true);
+        display.scroll_down(3);
+
+        assert!(!display.pixel(5, 0));
+        assert!(display.pixel(5, 3));
+        assert!(!display.pixel(5, LORES_HEIGHT - 2));
+
+        for y in 0..3 {
+            for x in 0..LORES_WIDTH {
+                assert!(!display.pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_up() {
+        let mut display = Display::new();
+
+        display.set_pixel(5, LORES_HEIGHT - 1, true);
+        display.set_pixel(5, 1, true);
+        display.scroll_up(3);
+
+        assert!(!display.pixel(5, LORES_HEIGHT - 1));
+        assert!(display.pixel(5, LORES_HEIGHT - 4));
+        assert!(!display.pixel(5, 1));
+
+        for y in LORES_HEIGHT - 3..LORES_HEIGHT {
+            for x in 0..LORES_WIDTH {
+                assert!(!display.pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_right() {
+        let mut display = Display::new();
+
+        display.set_pixel(0, 1, true);
+        display.set_pixel(LORES_WIDTH - 2, 1, true);
+        display.scroll_right(4);
+
+        assert!(!display.pixel(0, 1));
+        assert!(display.pixel(4, 1));
+        assert!(!display.pixel(LORES_WIDTH - 2, 1));
+        assert!(!display.pixel(1, 1));
+        assert!(!display.pixel(LORES_WIDTH - 1, 1));
+    }
+
+    #[test]
+    fn scroll_left() {
+        let mut display = Display::new();
+
+        display.set_pixel(LORES_WIDTH - 1, 1, true);
+        display.set_pixel(1, 1, true);
+        display.scroll_left(4);
+
+        assert!(!display.pixel(LORES_WIDTH - 1, 1));
+        assert!(display.pixel(LORES_WIDTH - 5, 1));
+        assert!(!display.pixel(1, 1));
+        assert!(!display.pixel(0, 1));
+    }
+
+    #[test]
+    fn clear() {
+        let mut display = Display::new();
+
+        display.set_pixel(0, 0, true);
+        display.set_pixel(LORES_WIDTH - 1, LORES_HEIGHT - 1, true);
+        display.clear();
+
+        assert!(!display.pixel(0, 0));
+        assert!(!display.pixel(LORES_WIDTH - 1, LORES_HEIGHT - 1));
+    }
+    #[test]
+    fn select_planes() {
+        let mut display = Display::new();
+
+        assert_eq!(display.selected_planes(), 1);
+
+        display.select_planes(0xff);
+
+        assert_eq!(display.selected_planes(), ALL_PLANES);
+
+        display.select_planes(0b10);
+        display.set_pixel(1, 1, true);
+
+        assert!(display.pixel(1, 1));
+        assert!(!display.plane_pixel(0, 1, 1));
+        assert!(display.plane_pixel(1, 1, 1));
+        assert_eq!(display.planes(1, 1), 0b10);
+
+        display.reset();
+
+        assert_eq!(display.selected_planes(), 1);
+        assert!(!display.pixel(1, 1));
+    }
+
+    #[test]
+    fn plane_operations() {
+        let mut display = Display::new();
+
+        display.select_planes(0b11);
+        display.set_pixel(4, 4, true);
+        display.select_planes(0b01);
+
+        assert!(display.xor_pixel(4, 4));
+        assert_eq!(display.planes(4, 4), 0b10);
+
+        display.select_planes(0b11);
+        display.scroll_down(1);
+        display.select_planes(0b10);
+        display.scroll_right(4);
+
+        assert_eq!(display.planes(4, 4), 0);
+        assert_eq!(display.planes(8, 5), 0b10);
+
+        display.select_planes(0b01);
+        display.clear();
+
+        assert_eq!(display.planes(8, 5), 0b10);
+
+        display.select_planes(0b10);
+        display.clear();
+
+        assert_eq!(display.planes(8, 5), 0);
+    }
+
+    #[test]
+    fn xor_plane_pixel() {
+        let mut display = Display::new();
+
+        assert!(!display.xor_plane_pixel(2, 0, 0));
+        assert_eq!(display.planes(0, 0), 0b100);
+        assert!(!display.xor_plane_pixel(3, 0, 0));
+        assert_eq!(display.planes(0, 0), 0b1100);
+        assert!(display.xor_plane_pixel(2, 0, 0));
+        assert_eq!(display.planes(0, 0), 0b1000);
+    }
+
+    #[test]
+    fn to_ascii() {
+        let mut display = Display::new();
+
+        display.set_pixel(1, 0, true);
+        display.set_pixel(0, 1, true);
+
+        let ascii = display.to_ascii_with('#', '.');
+        let lines: Vec<&str> = ascii.lines().collect();
+
+        assert_eq!(lines.len(), LORES_HEIGHT);
+        assert!(ascii.ends_with('\n'));
+        assert_eq!(lines[0], format!(".#{}", ".".repeat(LORES_WIDTH - 2)));
+        assert_eq!(lines[1], format!("#.{}", ".".repeat(LORES_WIDTH - 2)));
+        assert_eq!(lines[2], ".".repeat(LORES_WIDTH));
+    }
+
+    #[test]
+    fn to_rgba() {
+        let mut display = Display::new();
+        let on = [0xff, 0xff, 0xff, 0xff];
+        let off = [0x00, 0x00, 0x00, 0xff];
+
+        display.set_pixel(1, 0, true);
+        display.select_planes(0b10);
+        display.set_pixel(0, 1, true);
+
+        let rgba = display.to_rgba(on, off);
+
+        assert_eq!(rgba.len(), LORES_WIDTH * LORES_HEIGHT * 4);
+        assert_eq!(rgba[0..4], off);
+        assert_eq!(rgba[4..8], on);
+
+        let offset = LORES_WIDTH * 4;
+        assert_eq!(rgba[offset..offset + 4], DEFAULT_PALETTE[0b10]);
+    }
+
+    #[test]
+    fn to_rgba_palette() {
+        let mut display = Display::new();
+        let mut palette = DEFAULT_PALETTE;
+        palette[0b11] = [0x12, 0x34, 0x56, 0xff];
+
+        assert_eq!(display.palette(), &DEFAULT_PALETTE);
+
+        display.set_palette(&palette);
+
+        for planes in 1..=ALL_PLANES {
+            display.select_planes(planes);
+            display.set_pixel(planes as usize, 0, true);
+        }
+
+        let rgba = display.to_rgba(palette[1], palette[0]);
+
+        assert_eq!(rgba.len(), LORES_WIDTH * LORES_HEIGHT * 4);
+        for (x, color) in palette.iter().enumerate() {
+            assert_eq!(rgba[x * 4..x * 4 + 4], *color);
+        }
+
+        // on and off only replace the plane 1 and unlit colors
+        let rgba = display.to_rgba([0xff; 4], [0; 4]);
+
+        assert_eq!(rgba[0..4], [0; 4]);
+        assert_eq!(rgba[4..8], [0xff; 4]);
+        assert_eq!(rgba[12..16], palette[0b11]);
+
+        display.reset();
+
+        assert_eq!(display.palette(), &palette);
+    }
+
+    #[test]
+    fn persistence() {
+        let mut display = Display::new();
+
+        display.set_pixel(1, 0, true);
+        display.clear();
+
+        assert_eq!(display.age(1, 0), 0);
+
+        display.set_persistence(1);
+        display.set_pixel(1, 0, true);
+        display.xor_pixel(2, 0);
+
+        assert_eq!(display.age(1, 0), 0);
+
+        display.clear();
+
+        assert_eq!(display.age(1, 0), 1);
+        assert_eq!(display.age(2, 0), 1);
+        assert_eq!(display.age(3, 0), 0);
+        assert_eq!(display.to_rgba([0xff; 4], [0; 4])[4..8], [0x7f; 4]);
+
+        display.tick_persistence();
+
+        assert_eq!(display.age(1, 0), 0);
+        assert_eq!(display.to_rgba([0xff; 4], [0; 4])[4..8], [0; 4]);
+
+        display.set_persistence(3);
+        display.set_pixel(1, 0, true);
+        display.scroll_right(4);
+
+        assert_eq!(display.age(1, 0), 3);
+        assert_eq!(display.age(5, 0), 0);
+        assert_eq!(display.to_rgba([0xff; 4], [0; 4])[4..8], [0xbf; 4]);
+    }
+
+    #[test]
+    fn dirty() {
+        let mut display = Display::new();
+
+        assert!(!display.is_dirty());
+        assert_eq!(display.dirty_region(), None);
+
+        display.xor_pixel(4, 2);
+
+        assert!(display.is_dirty());
+        assert_eq!(
+            display.dirty_region(),
+            Some(DirtyRegion {
+                x: 4,
+                y: 2,
+                width: 1,
+                height: 1
+            })
+        );
+
+        display.set_pixel(1, 6, true);
+        display.xor_plane_pixel(1, 9, 3);
+
+        assert_eq!(
+            display.dirty_region(),
+            Some(DirtyRegion {
+                x: 1,
+                y: 2,
+                width: 9,
+                height: 5
+            })
+        );
+
+        display.clear_dirty();
+
+        assert!(!display.is_dirty());
+
+        display.clear();
+
+        assert_eq!(
+            display.dirty_region(),
+            Some(DirtyRegion {
+                x: 0,
+                y: 0,
+                width: LORES_WIDTH,
+                height: LORES_HEIGHT
+            })
+        );
+
+        display.clear_dirty();
+        display.scroll_left(4);
+
+        assert!(display.is_dirty());
+
+        display.clear_dirty();
+        display.set_resolution(Resolution::Hires);
+
+        assert_eq!(
+            display.dirty_region(),
+            Some(DirtyRegion {
+                x: 0,
+                y: 0,
+                width: HIRES_WIDTH,
+                height: HIRES_HEIGHT
+            })
+        );
+    }
+
+    #[test]
+    fn set_pixels() {
+        let mut display = Display::new();
+
+        assert_eq!(display.set_pixels().count(), 0);
+
+        display.set_pixel(5, 0, true);
+        display.set_pixel(63, 31, true);
+        display.set_pixel(0, 10, true);
+        display.select_planes(2);
+        display.set_pixel(7, 10, true);
+
+        let pixels: Vec<(u16, u16)> = display.set_pixels().collect();
+
+        assert_eq!(pixels, [(5, 0), (0, 10), (7, 10), (63, 31)]);
+
+        display.set_resolution(Resolution::Hires);
+        display.set_pixel(127, 63, true);
+
+        assert_eq!(display.set_pixels().collect::<Vec<_>>(), [(127, 63)]);
+    }
+}
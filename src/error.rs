@@ -0,0 +1,61 @@
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VmError {
+    InvalidOpcode(u16),
+    EmptyRom,
+    OddRomLength(usize),
+    RomTooLarge { size: usize, capacity: usize },
+    InvalidEntry(u16),
+    InvalidStackSize(usize),
+    StackOverflow,
+    StackUnderflow,
+    UnsupportedSys(u16),
+    MisalignedPc(u16),
+    ExecutingData(u16),
+    KeyWaitTimeout,
+    WriteProtected(u16),
+    OutOfBounds { address: usize, size: usize },
+    InvalidSnapshot,
+    VersionMismatch { expected: u16, found: u16 },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::InvalidOpcode(opcode) => write!(f, "Invalid opcode {:#06x}", opcode),
+            VmError::EmptyRom => write!(f, "Empty ROM"),
+            VmError::OddRomLength(size) => write!(f, "Odd ROM length of {} bytes", size),
+            VmError::InvalidEntry(entry) => write!(f, "Entry {:#06x} is outside memory", entry),
+            VmError::InvalidStackSize(size) => write!(f, "Stack size {} is out of range", size),
+            VmError::StackOverflow => write!(f, "Stack overflow"),
+            VmError::StackUnderflow => write!(f, "Stack underflow"),
+            VmError::UnsupportedSys(address) => {
+                write!(f, "Unsupported machine code call to {:#05x}", address)
+            }
+            VmError::MisalignedPc(pc) => write!(f, "Misaligned pc {:#06x}", pc),
+            VmError::ExecutingData(pc) => write!(f, "Executing data at {:#06x}", pc),
+            VmError::KeyWaitTimeout => write!(f, "Timed out waiting for a key"),
+            VmError::WriteProtected(address) => {
+                write!(f, "Write to protected address {:#05x}", address)
+            }
+            VmError::OutOfBounds { address, size } => {
+                write!(f, "{} bytes at {:#05x} do not fit in memory", size, address)
+            }
+            VmError::InvalidSnapshot => write!(f, "Invalid snapshot"),
+            VmError::VersionMismatch { expected, found } => write!(
+                f,
+                "Snapshot version {} does not match the supported version {}",
+                found, expected
+            ),
+            VmError::RomTooLarge { size, capacity } => write!(
+                f,
+                "ROM of {} bytes exceeds the {} bytes available",
+                size, capacity
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VmError {}
@@ -0,0 +1,150 @@
+use crate::vm::VM;
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+// Forwards key changes to the VM keypad, logging each one with VM::cycles at that point
+#[derive(Clone, Default)]
+pub struct InputRecorder {
+    events: Vec<InputEvent>,
+}
+
+impl InputRecorder {
+    pub fn new() -> InputRecorder {
+        InputRecorder::default()
+    }
+
+    pub fn key_down(&mut self, vm: &mut VM, key: u8) {
+        self.record(vm, key, true);
+    }
+
+    pub fn key_up(&mut self, vm: &mut VM, key: u8) {
+        self.record(vm, key, false);
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    pub fn into_replay(self) -> InputReplay {
+        InputReplay::new(self.events)
+    }
+
+    fn record(&mut self, vm: &mut VM, key: u8, pressed: bool) {
+        self.events.push(InputEvent {
+            cycle: vm.cycles(),
+            key,
+            pressed,
+        });
+
+        apply_event(vm, key, pressed);
+    }
+}
+
+// Feeds a recorded log back, call apply wherever the recording fed its input (usually
+// before every VM::run_frame) on a VM started from the same ROM and seed
+#[derive(Clone)]
+pub struct InputReplay {
+    events: Vec<InputEvent>,
+    next: usize,
+}
+
+impl InputReplay {
+    pub fn new(events: Vec<InputEvent>) -> InputReplay {
+        InputReplay { events, next: 0 }
+    }
+
+    // Applies every pending event recorded at or before the current VM cycle
+    pub fn apply(&mut self, vm: &mut VM) {
+        while let Some(event) = self.events.get(self.next) {
+            if event.cycle > vm.cycles() {
+                break;
+            }
+
+            apply_event(vm, event.key, event.pressed);
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
+
+fn apply_event(vm: &mut VM, key: u8, pressed: bool) {
+    if pressed {
+        vm.keypad.key_down(key);
+    } else {
+        vm.keypad.key_up(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_program(vm: &mut VM) {
+        vm.write_u16(0x200, 0x6005); // LD V0, 0x05
+        vm.write_u16(0x202, 0xe0a1); // SKNP V0
+        vm.write_u16(0x204, 0x2208); // CALL 0x208
+        vm.write_u16(0x206, 0x1202); // JP 0x202
+        vm.write_u16(0x208, 0xc13f); // RND V1, 0x3f
+        vm.write_u16(0x20a, 0xc21f); // RND V2, 0x1f
+        vm.write_u16(0x20c, 0xa050); // LD I, 0x050
+        vm.write_u16(0x20e, 0xd125); // DRW V1, V2, 5
+        vm.write_u16(0x210, 0x00ee); // RET
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut vm = VM::new();
+        let mut recorder = InputRecorder::new();
+
+        load_program(&mut vm);
+
+        for frame in 0..30 {
+            match frame {
+                3 | 12 => recorder.key_down(&mut vm, 0x5),
+                5 | 20 => recorder.key_up(&mut vm, 0x5),
+                8 => recorder.key_down(&mut vm, 0x1),
+                _ => {}
+            }
+
+            vm.run_frame(10);
+        }
+
+        assert_eq!(recorder.events().len(), 5);
+        assert_eq!(
+            recorder.events()[0],
+            InputEvent {
+                cycle: 30,
+                key: 0x5,
+                pressed: true
+            }
+        );
+        assert!(vm.display.to_ascii().contains('█'));
+
+        let mut replayed = VM::new();
+        let mut replay = recorder.into_replay();
+
+        load_program(&mut replayed);
+
+        for _ in 0..30 {
+            replay.apply(&mut replayed);
+            replayed.run_frame(10);
+        }
+
+        assert!(replay.is_finished());
+        assert_eq!(
+            replayed.display.to_rgba([0xff; 4], [0; 4]),
+            vm.display.to_rgba([0xff; 4], [0; 4])
+        );
+        assert_eq!(replayed.regs.v, vm.regs.v);
+        assert!(replayed.keypad.is_pressed(0x1));
+    }
+}
@@ -0,0 +1,699 @@
+use crate::error::VmError;
+use core::{fmt, mem};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    // 0nnn - SYS addr
+    Sys(u16),
+    // 00Cn - SCD nibble
+    Scd(u8),
+    // 00Dn - SCU nibble
+    Scu(u8),
+    // 00E0 - CLS
+    Cls,
+    // 00EE - RET
+    Ret,
+    // 00FB - SCR
+    Scr,
+    // 00FC - SCL
+    Scl,
+    // 00FD - EXIT
+    Exit,
+    // 00FE - LOW
+    Low,
+    // 00FF - HIGH
+    High,
+    // 1nnn - JP addr
+    Jp(u16),
+    // 2nnn - CALL addr
+    Call(u16),
+    // 3xkk - SE Vx, byte
+    SeByte(u8, u8),
+    // 4xkk - SNE Vx, byte
+    SneByte(u8, u8),
+    // 5xy0 - SE Vx, Vy
+    SeReg(u8, u8),
+    // 6xkk - LD Vx, byte
+    LdByte(u8, u8),
+    // 7xkk - ADD Vx, byte
+    AddByte(u8, u8),
+    // 8xy0 - LD Vx, Vy
+    LdReg(u8, u8),
+    // 8xy1 - OR Vx, Vy
+    Or(u8, u8),
+    // 8xy2 - AND Vx, Vy
+    And(u8, u8),
+    // 8xy3 - XOR Vx, Vy
+    Xor(u8, u8),
+    // 8xy4 - ADD Vx, Vy
+    AddReg(u8, u8),
+    // 8xy5 - SUB Vx, Vy
+    Sub(u8, u8),
+    // 8xy6 - SHR Vx {, Vy}
+    Shr(u8, u8),
+    // 8xy7 - SUBN Vx, Vy
+    Subn(u8, u8),
+    // 8xyE - SHL Vx {, Vy}
+    Shl(u8, u8),
+    // 9xy0 - SNE Vx, Vy
+    SneReg(u8, u8),
+    // Annn - LD I, addr
+    LdI(u16),
+    // Bnnn - JP V0, addr
+    JpV0(u16),
+    // Cxkk - RND Vx, byte
+    Rnd(u8, u8),
+    // Dxyn - DRW Vx, Vy, nibble
+    Drw(u8, u8, u8),
+    // Ex9E - SKP Vx
+    Skp(u8),
+    // ExA1 - SKNP Vx
+    Sknp(u8),
+    // F000 nnnn - LD I, long addr, the address is the word following the opcode
+    LdILong,
+    // Fx01 - PLANE n
+    Plane(u8),
+    // F002 - AUDIO
+    Audio,
+    // Fx07 - LD Vx, DT
+    LdVxDt(u8),
+    // Fx0A - LD Vx, K
+    LdVxK(u8),
+    // Fx15 - LD DT, Vx
+    LdDtVx(u8),
+    // Fx18 - LD ST, Vx
+    LdStVx(u8),
+    // Fx1E - ADD I, Vx
+    AddI(u8),
+    // Fx29 - LD F, Vx
+    LdF(u8),
+    // Fx30 - LD HF, Vx
+    LdHf(u8),
+    // Fx33 - LD B, Vx
+    LdB(u8),
+    // Fx3A - PITCH Vx
+    Pitch(u8),
+    // Fx55 - LD [I], Vx
+    LdIVx(u8),
+    // Fx65 - LD Vx, [I]
+    LdVxI(u8),
+    // Fx75 - LD R, Vx
+    LdRVx(u8),
+    // Fx85 - LD Vx, R
+    LdVxR(u8),
+}
+
+// Every instruction with its pattern and the mask of the bits identifying it, an opcode is
+// that instruction when `opcode & mask == pattern`. SYS overlaps the other 0x0 instructions
+// and only applies when none of them matches. decode picks the most specific matching entry
+// and encode fills the operands into the pattern
+pub const OPCODE_TABLE: [(Instruction, u16, u16); 49] = [
+    (Instruction::Sys(0), 0x0000, 0xf000),
+    (Instruction::Scd(0), 0x00c0, 0xfff0),
+    (Instruction::Scu(0), 0x00d0, 0xfff0),
+    (Instruction::Cls, 0x00e0, 0xffff),
+    (Instruction::Ret, 0x00ee, 0xffff),
+    (Instruction::Scr, 0x00fb, 0xffff),
+    (Instruction::Scl, 0x00fc, 0xffff),
+    (Instruction::Exit, 0x00fd, 0xffff),
+    (Instruction::Low, 0x00fe, 0xffff),
+    (Instruction::High, 0x00ff, 0xffff),
+    (Instruction::Jp(0), 0x1000, 0xf000),
+    (Instruction::Call(0), 0x2000, 0xf000),
+    (Instruction::SeByte(0, 0), 0x3000, 0xf000),
+    (Instruction::SneByte(0, 0), 0x4000, 0xf000),
+    (Instruction::SeReg(0, 0), 0x5000, 0xf00f),
+    (Instruction::LdByte(0, 0), 0x6000, 0xf000),
+    (Instruction::AddByte(0, 0), 0x7000, 0xf000),
+    (Instruction::LdReg(0, 0), 0x8000, 0xf00f),
+    (Instruction::Or(0, 0), 0x8001, 0xf00f),
+    (Instruction::And(0, 0), 0x8002, 0xf00f),
+    (Instruction::Xor(0, 0), 0x8003, 0xf00f),
+    (Instruction::AddReg(0, 0), 0x8004, 0xf00f),
+    (Instruction::Sub(0, 0), 0x8005, 0xf00f),
+    (Instruction::Shr(0, 0), 0x8006, 0xf00f),
+    (Instruction::Subn(0, 0), 0x8007, 0xf00f),
+    (Instruction::Shl(0, 0), 0x800e, 0xf00f),
+    (Instruction::SneReg(0, 0), 0x9000, 0xf00f),
+    (Instruction::LdI(0), 0xa000, 0xf000),
+    (Instruction::JpV0(0), 0xb000, 0xf000),
+    (Instruction::Rnd(0, 0), 0xc000, 0xf000),
+    (Instruction::Drw(0, 0, 0), 0xd000, 0xf000),
+    (Instruction::Skp(0), 0xe09e, 0xf0ff),
+    (Instruction::Sknp(0), 0xe0a1, 0xf0ff),
+    (Instruction::LdILong, 0xf000, 0xffff),
+    (Instruction::Plane(0), 0xf001, 0xf0ff),
+    (Instruction::Audio, 0xf002, 0xffff),
+    (Instruction::LdVxDt(0), 0xf007, 0xf0ff),
+    (Instruction::LdVxK(0), 0xf00a, 0xf0ff),
+    (Instruction::LdDtVx(0), 0xf015, 0xf0ff),
+    (Instruction::LdStVx(0), 0xf018, 0xf0ff),
+    (Instruction::AddI(0), 0xf01e, 0xf0ff),
+    (Instruction::LdF(0), 0xf029, 0xf0ff),
+    (Instruction::LdHf(0), 0xf030, 0xf0ff),
+    (Instruction::LdB(0), 0xf033, 0xf0ff),
+    (Instruction::Pitch(0), 0xf03a, 0xf0ff),
+    (Instruction::LdIVx(0), 0xf055, 0xf0ff),
+    (Instruction::LdVxI(0), 0xf065, 0xf0ff),
+    (Instruction::LdRVx(0), 0xf075, 0xf0ff),
+    (Instruction::LdVxR(0), 0xf085, 0xf0ff),
+];
+
+const INVALID: u8 = 0xff;
+
+// OPCODE_TABLE index of the most specific entry matching an opcode, or INVALID when none does.
+// Indexed by whether x is zero and then by the high nibble and the low byte, which is all an
+// entry's bits depend on since the masks cover x either fully or not at all
+static DECODE_INDEX: [[u8; 4096]; 2] = {
+    let mut index = [[INVALID; 4096]; 2];
+
+    let mut entry = 0;
+    while entry < OPCODE_TABLE.len() {
+        let (_, pattern, mask) = OPCODE_TABLE[entry];
+
+        let mut zero = 0;
+        while zero < 2 {
+            let x = if zero == 1 { 0x0000 } else { 0x0f00 };
+
+            let mut low = 0;
+            while low < 0x100 {
+                let opcode = pattern & 0xf000 | x | low;
+                let key = (opcode >> 4 & 0x0f00 | low) as usize;
+                let current = index[zero][key];
+
+                if opcode & mask == pattern
+                    && (current == INVALID
+                        || OPCODE_TABLE[current as usize].2.count_ones() < mask.count_ones())
+                {
+                    index[zero][key] = entry as u8;
+                }
+
+                low += 1;
+            }
+
+            zero += 1;
+        }
+
+        entry += 1;
+    }
+
+    index
+};
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Result<Instruction, VmError> {
+        let zero = (x(opcode) == 0) as usize;
+        let key = (opcode >> 4 & 0x0f00 | opcode & 0x00ff) as usize;
+
+        match DECODE_INDEX[zero][key] {
+            INVALID => Err(VmError::InvalidOpcode(opcode)),
+            entry => Ok(OPCODE_TABLE[entry as usize].0.with_operands(opcode)),
+        }
+    }
+
+    // Approximate COSMAC VIP machine cycles (8 clocks at 1.76 MHz) including fetch and decode,
+    // DRW is dominated by waiting for the vertical blank, extensions cost like a simple opcode
+    pub fn vip_cycles(&self) -> u32 {
+        match *self {
+            Instruction::Cls => 24,
+            Instruction::Ret | Instruction::Jp(_) | Instruction::Call(_) | Instruction::JpV0(_) => {
+                23
+            }
+            Instruction::SeByte(..) | Instruction::SneByte(..) | Instruction::LdI(_) => 12,
+            Instruction::SeReg(..)
+            | Instruction::SneReg(..)
+            | Instruction::Skp(_)
+            | Instruction::Sknp(_) => 16,
+            Instruction::LdByte(..) => 6,
+            Instruction::AddByte(..)
+            | Instruction::LdVxDt(_)
+            | Instruction::LdVxK(_)
+            | Instruction::LdDtVx(_)
+            | Instruction::LdStVx(_) => 10,
+            Instruction::LdReg(..)
+            | Instruction::Or(..)
+            | Instruction::And(..)
+            | Instruction::Xor(..)
+            | Instruction::AddReg(..)
+            | Instruction::Sub(..)
+            | Instruction::Shr(..)
+            | Instruction::Subn(..)
+            | Instruction::Shl(..) => 44,
+            Instruction::Rnd(..) => 36,
+            Instruction::Drw(..) => 5008,
+            Instruction::AddI(_) => 19,
+            Instruction::LdF(_) => 20,
+            Instruction::LdB(_) => 204,
+            Instruction::LdIVx(x) | Instruction::LdVxI(x) => 5 + 8 * ((x & 0xf) as u32 + 1),
+            _ => 12,
+        }
+    }
+
+    // Bits of the opcode that identify the instruction, see OPCODE_TABLE
+    pub fn mask(&self) -> u16 {
+        self.entry().2
+    }
+
+    // Opcode with every operand masked out, e.g. 0x8006 for any SHR Vx {, Vy}
+    pub fn pattern(&self) -> u16 {
+        self.entry().1
+    }
+
+    // Inverse of decode, operands are masked to the bits their field holds
+    pub fn encode(&self) -> u16 {
+        self.pattern() | self.operands()
+    }
+
+    // The OPCODE_TABLE entry of this kind of instruction, the table lists every variant
+    fn entry(&self) -> &'static (Instruction, u16, u16) {
+        let kind = mem::discriminant(self);
+
+        OPCODE_TABLE
+            .iter()
+            .find(|(instruction, _, _)| mem::discriminant(instruction) == kind)
+            .expect("every instruction has an OPCODE_TABLE entry")
+    }
+
+    // Same kind of instruction with the operands taken from the opcode
+    fn with_operands(&self, opcode: u16) -> Instruction {
+        let (x, y, n, kk, nnn) = (x(opcode), y(opcode), n(opcode), kk(opcode), nnn(opcode));
+
+        match *self {
+            Instruction::Sys(_) => Instruction::Sys(nnn),
+            Instruction::Scd(_) => Instruction::Scd(n),
+            Instruction::Scu(_) => Instruction::Scu(n),
+            Instruction::Jp(_) => Instruction::Jp(nnn),
+            Instruction::Call(_) => Instruction::Call(nnn),
+            Instruction::SeByte(..) => Instruction::SeByte(x, kk),
+            Instruction::SneByte(..) => Instruction::SneByte(x, kk),
+            Instruction::SeReg(..) => Instruction::SeReg(x, y),
+            Instruction::LdByte(..) => Instruction::LdByte(x, kk),
+            Instruction::AddByte(..) => Instruction::AddByte(x, kk),
+            Instruction::LdReg(..) => Instruction::LdReg(x, y),
+            Instruction::Or(..) => Instruction::Or(x, y),
+            Instruction::And(..) => Instruction::And(x, y),
+            Instruction::Xor(..) => Instruction::Xor(x, y),
+            Instruction::AddReg(..) => Instruction::AddReg(x, y),
+            Instruction::Sub(..) => Instruction::Sub(x, y),
+            Instruction::Shr(..) => Instruction::Shr(x, y),
+            Instruction::Subn(..) => Instruction::Subn(x, y),
+            Instruction::Shl(..) => Instruction::Shl(x, y),
+            Instruction::SneReg(..) => Instruction::SneReg(x, y),
+            Instruction::LdI(_) => Instruction::LdI(nnn),
+            Instruction::JpV0(_) => Instruction::JpV0(nnn),
+            Instruction::Rnd(..) => Instruction::Rnd(x, kk),
+            Instruction::Drw(..) => Instruction::Drw(x, y, n),
+            Instruction::Skp(_) => Instruction::Skp(x),
+            Instruction::Sknp(_) => Instruction::Sknp(x),
+            Instruction::Plane(_) => Instruction::Plane(x),
+            Instruction::LdVxDt(_) => Instruction::LdVxDt(x),
+            Instruction::LdVxK(_) => Instruction::LdVxK(x),
+            Instruction::LdDtVx(_) => Instruction::LdDtVx(x),
+            Instruction::LdStVx(_) => Instruction::LdStVx(x),
+            Instruction::AddI(_) => Instruction::AddI(x),
+            Instruction::LdF(_) => Instruction::LdF(x),
+            Instruction::LdHf(_) => Instruction::LdHf(x),
+            Instruction::LdB(_) => Instruction::LdB(x),
+            Instruction::Pitch(_) => Instruction::Pitch(x),
+            Instruction::LdIVx(_) => Instruction::LdIVx(x),
+            Instruction::LdVxI(_) => Instruction::LdVxI(x),
+            Instruction::LdRVx(_) => Instruction::LdRVx(x),
+            Instruction::LdVxR(_) => Instruction::LdVxR(x),
+            Instruction::Cls
+            | Instruction::Ret
+            | Instruction::Scr
+            | Instruction::Scl
+            | Instruction::Exit
+            | Instruction::Low
+            | Instruction::High
+            | Instruction::LdILong
+            | Instruction::Audio => *self,
+        }
+    }
+
+    // The operands in their opcode bits
+    fn operands(&self) -> u16 {
+        let reg = |x: u8| ((x & 0xf) as u16) << 8;
+        let regs = |x: u8, y: u8| reg(x) | ((y & 0xf) as u16) << 4;
+
+        match *self {
+            Instruction::Sys(address)
+            | Instruction::Jp(address)
+            | Instruction::Call(address)
+            | Instruction::LdI(address)
+            | Instruction::JpV0(address) => address & 0x0fff,
+            Instruction::Scd(n) | Instruction::Scu(n) => (n & 0xf) as u16,
+            Instruction::SeByte(x, kk)
+            | Instruction::SneByte(x, kk)
+            | Instruction::LdByte(x, kk)
+            | Instruction::AddByte(x, kk)
+            | Instruction::Rnd(x, kk) => reg(x) | kk as u16,
+            Instruction::SeReg(x, y)
+            | Instruction::LdReg(x, y)
+            | Instruction::Or(x, y)
+            | Instruction::And(x, y)
+            | Instruction::Xor(x, y)
+            | Instruction::AddReg(x, y)
+            | Instruction::Sub(x, y)
+            | Instruction::Shr(x, y)
+            | Instruction::Subn(x, y)
+            | Instruction::Shl(x, y)
+            | Instruction::SneReg(x, y) => regs(x, y),
+            Instruction::Drw(x, y, n) => regs(x, y) | (n & 0xf) as u16,
+            Instruction::Skp(x)
+            | Instruction::Sknp(x)
+            | Instruction::Plane(x)
+            | Instruction::LdVxDt(x)
+            | Instruction::LdVxK(x)
+            | Instruction::LdDtVx(x)
+            | Instruction::LdStVx(x)
+            | Instruction::AddI(x)
+            | Instruction::LdF(x)
+            | Instruction::LdHf(x)
+            | Instruction::LdB(x)
+            | Instruction::Pitch(x)
+            | Instruction::LdIVx(x)
+            | Instruction::LdVxI(x)
+            | Instruction::LdRVx(x)
+            | Instruction::LdVxR(x) => reg(x),
+            Instruction::Cls
+            | Instruction::Ret
+            | Instruction::Scr
+            | Instruction::Scl
+            | Instruction::Exit
+            | Instruction::Low
+            | Instruction::High
+            | Instruction::LdILong
+            | Instruction::Audio => 0,
+        }
+    }
+}
+
+// Mnemonics as used in the opcode comments, e.g. "LD V1, 0x23" or "DRW V0, V1, 5"
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS {:#05x}", nnn),
+            Instruction::Scd(n) => write!(f, "SCD {}", n),
+            Instruction::Scu(n) => write!(f, "SCU {}", n),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Scr => write!(f, "SCR"),
+            Instruction::Scl => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Low => write!(f, "LOW"),
+            Instruction::High => write!(f, "HIGH"),
+            Instruction::Jp(nnn) => write!(f, "JP {:#05x}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL {:#05x}", nnn),
+            Instruction::SeByte(x, kk) => write!(f, "SE V{:X}, {:#04x}", x, kk),
+            Instruction::SneByte(x, kk) => write!(f, "SNE V{:X}, {:#04x}", x, kk),
+            Instruction::SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LdByte(x, kk) => write!(f, "LD V{:X}, {:#04x}", x, kk),
+            Instruction::AddByte(x, kk) => write!(f, "ADD V{:X}, {:#04x}", x, kk),
+            Instruction::LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LdI(nnn) => write!(f, "LD I, {:#05x}", nnn),
+            Instruction::JpV0(nnn) => write!(f, "JP V0, {:#05x}", nnn),
+            Instruction::Rnd(x, kk) => write!(f, "RND V{:X}, {:#04x}", x, kk),
+            Instruction::Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skp(x) => write!(f, "SKP V{:X}", x),
+            Instruction::Sknp(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LdILong => write!(f, "LD I, long"),
+            Instruction::Plane(x) => write!(f, "PLANE {}", x),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LdF(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LdHf(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::LdB(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::LdRVx(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LdVxR(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+// True when the opcode decodes to an instruction, without executing it
+pub fn is_valid_opcode(opcode: u16) -> bool {
+    Instruction::decode(opcode).is_ok()
+}
+
+fn x(opcode: u16) -> u8 {
+    ((opcode >> 8) & 0x000f) as u8
+}
+
+fn y(opcode: u16) -> u8 {
+    ((opcode >> 4) & 0x000f) as u8
+}
+
+fn n(opcode: u16) -> u8 {
+    (opcode & 0x000f) as u8
+}
+
+fn kk(opcode: u16) -> u8 {
+    (opcode & 0x00ff) as u8
+}
+
+fn nnn(opcode: u16) -> u16 {
+    opcode & 0x0fff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        assert_eq!(Instruction::decode(0x0123), Ok(Instruction::Sys(0x123)));
+        assert_eq!(Instruction::decode(0x00c4), Ok(Instruction::Scd(4)));
+        assert_eq!(Instruction::decode(0x00d2), Ok(Instruction::Scu(2)));
+        assert_eq!(Instruction::decode(0x00e0), Ok(Instruction::Cls));
+        assert_eq!(Instruction::decode(0x00ee), Ok(Instruction::Ret));
+        assert_eq!(Instruction::decode(0x00fb), Ok(Instruction::Scr));
+        assert_eq!(Instruction::decode(0x00fc), Ok(Instruction::Scl));
+        assert_eq!(Instruction::decode(0x00fd), Ok(Instruction::Exit));
+        assert_eq!(Instruction::decode(0x00fe), Ok(Instruction::Low));
+        assert_eq!(Instruction::decode(0x00ff), Ok(Instruction::High));
+        assert_eq!(Instruction::decode(0x1234), Ok(Instruction::Jp(0x234)));
+        assert_eq!(Instruction::decode(0x2345), Ok(Instruction::Call(0x345)));
+        assert_eq!(
+            Instruction::decode(0x3123),
+            Ok(Instruction::SeByte(1, 0x23))
+        );
+        assert_eq!(
+            Instruction::decode(0x4123),
+            Ok(Instruction::SneByte(1, 0x23))
+        );
+        assert_eq!(Instruction::decode(0x5120), Ok(Instruction::SeReg(1, 2)));
+        assert_eq!(
+            Instruction::decode(0x6123),
+            Ok(Instruction::LdByte(1, 0x23))
+        );
+        assert_eq!(
+            Instruction::decode(0x7123),
+            Ok(Instruction::AddByte(1, 0x23))
+        );
+        assert_eq!(Instruction::decode(0x8120), Ok(Instruction::LdReg(1, 2)));
+        assert_eq!(Instruction::decode(0x8121), Ok(Instruction::Or(1, 2)));
+        assert_eq!(Instruction::decode(0x8122), Ok(Instruction::And(1, 2)));
+        assert_eq!(Instruction::decode(0x8123), Ok(Instruction::Xor(1, 2)));
+        assert_eq!(Instruction::decode(0x8124), Ok(Instruction::AddReg(1, 2)));
+        assert_eq!(Instruction::decode(0x8125), Ok(Instruction::Sub(1, 2)));
+        assert_eq!(Instruction::decode(0x8126), Ok(Instruction::Shr(1, 2)));
+        assert_eq!(Instruction::decode(0x8127), Ok(Instruction::Subn(1, 2)));
+        assert_eq!(Instruction::decode(0x812e), Ok(Instruction::Shl(1, 2)));
+        assert_eq!(Instruction::decode(0x9120), Ok(Instruction::SneReg(1, 2)));
+        assert_eq!(Instruction::decode(0xa123), Ok(Instruction::LdI(0x123)));
+        assert_eq!(Instruction::decode(0xb123), Ok(Instruction::JpV0(0x123)));
+        assert_eq!(Instruction::decode(0xc123), Ok(Instruction::Rnd(1, 0x23)));
+        assert_eq!(Instruction::decode(0xd123), Ok(Instruction::Drw(1, 2, 3)));
+        assert_eq!(Instruction::decode(0xe19e), Ok(Instruction::Skp(1)));
+        assert_eq!(Instruction::decode(0xe1a1), Ok(Instruction::Sknp(1)));
+        assert_eq!(Instruction::decode(0xf000), Ok(Instruction::LdILong));
+        assert_eq!(Instruction::decode(0xf201), Ok(Instruction::Plane(2)));
+        assert_eq!(Instruction::decode(0xf002), Ok(Instruction::Audio));
+        assert_eq!(Instruction::decode(0xf107), Ok(Instruction::LdVxDt(1)));
+        assert_eq!(Instruction::decode(0xf10a), Ok(Instruction::LdVxK(1)));
+        assert_eq!(Instruction::decode(0xf115), Ok(Instruction::LdDtVx(1)));
+        assert_eq!(Instruction::decode(0xf118), Ok(Instruction::LdStVx(1)));
+        assert_eq!(Instruction::decode(0xf11e), Ok(Instruction::AddI(1)));
+        assert_eq!(Instruction::decode(0xf129), Ok(Instruction::LdF(1)));
+        assert_eq!(Instruction::decode(0xf130), Ok(Instruction::LdHf(1)));
+        assert_eq!(Instruction::decode(0xf133), Ok(Instruction::LdB(1)));
+        assert_eq!(Instruction::decode(0xf13a), Ok(Instruction::Pitch(1)));
+        assert_eq!(Instruction::decode(0xf155), Ok(Instruction::LdIVx(1)));
+        assert_eq!(Instruction::decode(0xf165), Ok(Instruction::LdVxI(1)));
+        assert_eq!(Instruction::decode(0xf175), Ok(Instruction::LdRVx(1)));
+        assert_eq!(Instruction::decode(0xf185), Ok(Instruction::LdVxR(1)));
+    }
+
+    #[test]
+    fn decode_invalid() {
+        for opcode in [
+            0x5121, 0x812f, 0x8128, 0x912f, 0xe100, 0xe19f, 0xf100, 0xf102, 0xf1ff,
+        ] {
+            assert_eq!(
+                Instruction::decode(opcode),
+                Err(VmError::InvalidOpcode(opcode))
+            );
+        }
+    }
+
+    #[test]
+    fn mnemonics() {
+        let mnemonics = [
+            (0x0123, "SYS 0x123"),
+            (0x00c4, "SCD 4"),
+            (0x00d2, "SCU 2"),
+            (0x00e0, "CLS"),
+            (0x00ee, "RET"),
+            (0x00fb, "SCR"),
+            (0x00fc, "SCL"),
+            (0x00fd, "EXIT"),
+            (0x00fe, "LOW"),
+            (0x00ff, "HIGH"),
+            (0x1234, "JP 0x234"),
+            (0x2045, "CALL 0x045"),
+            (0x3123, "SE V1, 0x23"),
+            (0x4a03, "SNE VA, 0x03"),
+            (0x5120, "SE V1, V2"),
+            (0x6123, "LD V1, 0x23"),
+            (0x7f23, "ADD VF, 0x23"),
+            (0x8120, "LD V1, V2"),
+            (0x8121, "OR V1, V2"),
+            (0x8122, "AND V1, V2"),
+            (0x8123, "XOR V1, V2"),
+            (0x8124, "ADD V1, V2"),
+            (0x8125, "SUB V1, V2"),
+            (0x8126, "SHR V1, V2"),
+            (0x8127, "SUBN V1, V2"),
+            (0x812e, "SHL V1, V2"),
+            (0x9120, "SNE V1, V2"),
+            (0xa050, "LD I, 0x050"),
+            (0xb123, "JP V0, 0x123"),
+            (0xc1ff, "RND V1, 0xff"),
+            (0xd12f, "DRW V1, V2, 15"),
+            (0xe19e, "SKP V1"),
+            (0xe1a1, "SKNP V1"),
+            (0xf000, "LD I, long"),
+            (0xf301, "PLANE 3"),
+            (0xf002, "AUDIO"),
+            (0xf107, "LD V1, DT"),
+            (0xf10a, "LD V1, K"),
+            (0xf115, "LD DT, V1"),
+            (0xf118, "LD ST, V1"),
+            (0xf11e, "ADD I, V1"),
+            (0xf129, "LD F, V1"),
+            (0xf130, "LD HF, V1"),
+            (0xf133, "LD B, V1"),
+            (0xf13a, "PITCH V1"),
+            (0xf155, "LD [I], V1"),
+            (0xf165, "LD V1, [I]"),
+            (0xf175, "LD R, V1"),
+            (0xf185, "LD V1, R"),
+        ];
+
+        for (opcode, mnemonic) in mnemonics {
+            assert_eq!(Instruction::decode(opcode).unwrap().to_string(), mnemonic);
+        }
+    }
+
+    #[test]
+    fn encode() {
+        for opcode in 0..=u16::MAX {
+            if let Ok(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{}", instruction);
+            }
+        }
+
+        assert_eq!(Instruction::Drw(0x11, 0x22, 0x33).encode(), 0xd123);
+        assert_eq!(Instruction::Jp(0xf234).encode(), 0x1234);
+    }
+
+    #[test]
+    fn pattern() {
+        let patterns = [
+            (0x0123, 0x0000),
+            (0x00c4, 0x00c0),
+            (0x00ee, 0x00ee),
+            (0x1234, 0x1000),
+            (0x5120, 0x5000),
+            (0x8126, 0x8006),
+            (0xd125, 0xd000),
+            (0xe39e, 0xe09e),
+            (0xf000, 0xf000),
+            (0xf133, 0xf033),
+        ];
+
+        for (opcode, pattern) in patterns.iter() {
+            assert_eq!(Instruction::decode(*opcode).unwrap().pattern(), *pattern);
+        }
+
+        // A pattern decodes to the same kind of instruction
+        for opcode in 0..=u16::MAX {
+            if let Ok(instruction) = Instruction::decode(opcode) {
+                let pattern = Instruction::decode(instruction.pattern()).unwrap();
+
+                assert_eq!(pattern.pattern(), instruction.pattern());
+            }
+        }
+    }
+
+    #[test]
+    fn valid_opcodes() {
+        for opcode in [
+            0x00e0, 0x0123, 0x1200, 0x800e, 0xe19e, 0xf000, 0xf002, 0xf385,
+        ]
+        .iter()
+        {
+            assert!(is_valid_opcode(*opcode), "{:#06x}", opcode);
+        }
+
+        for opcode in [
+            0x5121, 0x8008, 0x800f, 0x9abf, 0xe100, 0xe1ff, 0xf100, 0xf102, 0xf1ff,
+        ]
+        .iter()
+        {
+            assert!(!is_valid_opcode(*opcode), "{:#06x}", opcode);
+        }
+    }
+
+    #[test]
+    fn opcode_table() {
+        for (index, &(instruction, pattern, mask)) in OPCODE_TABLE.iter().enumerate() {
+            assert_eq!(instruction.entry(), &OPCODE_TABLE[index], "{}", instruction);
+            assert_eq!(instruction.encode(), pattern);
+            assert_eq!(instruction.mask(), mask);
+            assert_eq!(Instruction::decode(pattern), Ok(instruction));
+        }
+
+        // The most specific matching entry is the decoded instruction, opcodes matching none fail
+        for opcode in 0..=u16::MAX {
+            let entry = OPCODE_TABLE
+                .iter()
+                .filter(|&&(_, pattern, mask)| opcode & mask == pattern)
+                .max_by_key(|&&(_, _, mask)| mask.count_ones());
+
+            match (entry, Instruction::decode(opcode)) {
+                (Some(&(_, pattern, _)), Ok(instruction)) => {
+                    assert_eq!(instruction.pattern(), pattern, "{:#06x}", opcode)
+                }
+                (None, Err(error)) => assert_eq!(error, VmError::InvalidOpcode(opcode)),
+                (entry, decoded) => panic!("{:#06x}: {:?} decoded as {:?}", opcode, entry, decoded),
+            }
+        }
+    }
+}
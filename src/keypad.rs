@@ -0,0 +1,128 @@
+pub const KEY_COUNT: usize = 16;
+
+// Host key for every keypad key on the conventional QWERTY layout
+//
+// 1 2 3 4     1 2 3 C
+// Q W E R  -> 4 5 6 D
+// A S D F     7 8 9 E
+// Z X C V     A 0 B F
+pub const QWERTY_LAYOUT: [char; KEY_COUNT] = [
+    'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+];
+
+// Host key state the VM pulls at the start of every frame, see VM::attach_key_source
+pub trait KeySource {
+    fn is_key_down(&self, key: u8) -> bool;
+}
+
+// State of the 16-key hex keypad, keys are 0x0-0xf and masked to 4 bits
+#[derive(Clone, Default)]
+pub struct Keypad {
+    keys: [bool; KEY_COUNT],
+}
+
+impl Keypad {
+    pub fn new() -> Keypad {
+        Keypad::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.keys.fill(false);
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.keys[(key & 0x0f) as usize] = true;
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.keys[(key & 0x0f) as usize] = false;
+    }
+
+    // Copies the state of all 16 keys from `source`
+    pub fn sync(&mut self, source: &dyn KeySource) {
+        for (key, pressed) in self.keys.iter_mut().enumerate() {
+            *pressed = source.is_key_down(key as u8);
+        }
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0x0f) as usize]
+    }
+
+    // Case insensitive, None for keys outside the layout
+    pub fn from_qwerty(key: char) -> Option<u8> {
+        let key = key.to_ascii_lowercase();
+
+        QWERTY_LAYOUT
+            .iter()
+            .position(|&host| host == key)
+            .map(|key| key as u8)
+    }
+
+    pub fn to_qwerty(key: u8) -> char {
+        QWERTY_LAYOUT[(key & 0x0f) as usize]
+    }
+
+    // Lowest pressed key, if any
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|&pressed| pressed)
+            .map(|key| key as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initialize() {
+        let keypad = Keypad::new();
+
+        for key in 0..KEY_COUNT as u8 {
+            assert!(!keypad.is_pressed(key));
+        }
+    }
+
+    #[test]
+    fn key_down_up() {
+        let mut keypad = Keypad::new();
+
+        keypad.key_down(0x5);
+        keypad.key_down(0xf);
+
+        assert!(keypad.is_pressed(0x5));
+        assert!(keypad.is_pressed(0xf));
+        assert!(!keypad.is_pressed(0x4));
+
+        keypad.key_up(0x5);
+
+        assert!(!keypad.is_pressed(0x5));
+        assert!(keypad.is_pressed(0xf));
+
+        keypad.key_down(0x1a);
+
+        assert!(keypad.is_pressed(0xa));
+        assert_eq!(keypad.first_pressed(), Some(0xa));
+
+        keypad.reset();
+
+        assert!(!keypad.is_pressed(0xa));
+        assert!(!keypad.is_pressed(0xf));
+        assert_eq!(keypad.first_pressed(), None);
+    }
+
+    #[test]
+    fn qwerty() {
+        assert_eq!(Keypad::from_qwerty('q'), Some(0x4));
+        assert_eq!(Keypad::from_qwerty('v'), Some(0xf));
+        assert_eq!(Keypad::from_qwerty('X'), Some(0x0));
+        assert_eq!(Keypad::from_qwerty('4'), Some(0xc));
+        assert_eq!(Keypad::from_qwerty('p'), None);
+
+        for key in 0..KEY_COUNT as u8 {
+            assert_eq!(Keypad::from_qwerty(Keypad::to_qwerty(key)), Some(key));
+        }
+    }
+}
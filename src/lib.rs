@@ -0,0 +1,20 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod assembler;
+pub mod bus;
+pub mod clock;
+pub mod disassembler;
+pub mod display;
+pub mod error;
+pub mod font;
+pub mod input;
+pub mod instruction;
+pub mod keypad;
+pub mod quirks;
+pub mod renderer;
+pub mod snapshot;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,5 +1,4 @@
-mod vm;
-use vm::*;
+use dale::vm::*;
 
 fn main() {
     let mut vm = VM::new();
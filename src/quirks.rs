@@ -0,0 +1,174 @@
+use crate::instruction::Instruction;
+use core::fmt;
+
+// Behaviour that differs between CHIP-8 interpreters, all off by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    // DRW waits for the vertical blank, at most one sprite is drawn per tick_timers
+    pub display_wait: bool,
+    // Sprite pixels past an edge wrap to the opposite side instead of being clipped
+    pub sprite_wrap: bool,
+    // 00Cn, 00Dn, 00FB and 00FC move by half as many pixels in lores, like SUPER-CHIP 1.1 which
+    // counts them in hires pixels
+    pub lores_scroll_half: bool,
+    // Sprite rows past the bottom of the active resolution, row 32 in lores and row 64 in hires,
+    // are clipped. sprite_wrap still decides the columns, so with both on sprites wrap sideways
+    // only, and with sprite_wrap off every edge clips either way
+    pub sprite_clip_bottom: bool,
+}
+
+impl Quirks {
+    // COSMAC VIP
+    pub const CHIP8: Quirks = Quirks {
+        display_wait: true,
+        sprite_wrap: false,
+        lores_scroll_half: false,
+        sprite_clip_bottom: false,
+    };
+
+    // SUPER-CHIP 1.1 on the HP 48
+    pub const SUPER_CHIP: Quirks = Quirks {
+        display_wait: false,
+        sprite_wrap: false,
+        lores_scroll_half: true,
+        sprite_clip_bottom: true,
+    };
+
+    // Octo
+    pub const XO_CHIP: Quirks = Quirks {
+        display_wait: false,
+        sprite_wrap: true,
+        lores_scroll_half: false,
+        sprite_clip_bottom: false,
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Chip8Variant {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Chip8Variant::Chip8 => Quirks::CHIP8,
+            Chip8Variant::SuperChip => Quirks::SUPER_CHIP,
+            Chip8Variant::XoChip => Quirks::XO_CHIP,
+        }
+    }
+}
+
+// Names as shown to users, e.g. "Detected: XO-CHIP"
+impl fmt::Display for Chip8Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Variant::Chip8 => write!(f, "CHIP-8"),
+            Chip8Variant::SuperChip => write!(f, "SUPER-CHIP"),
+            Chip8Variant::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+pub fn detect_profile(rom: &[u8]) -> Quirks {
+    detect_variant(rom).quirks()
+}
+
+// Guesses the interpreter a ROM was written for from the opcodes at even offsets, data that
+// happens to look like an extension opcode can mislead it
+pub fn detect_variant(rom: &[u8]) -> Chip8Variant {
+    let mut super_chip = false;
+
+    for word in rom.chunks_exact(2) {
+        let instruction = match Instruction::decode((word[0] as u16) << 8 | word[1] as u16) {
+            Ok(instruction) => instruction,
+            Err(_) => continue,
+        };
+
+        match instruction {
+            Instruction::Scu(_)
+            | Instruction::LdILong
+            | Instruction::Plane(_)
+            | Instruction::Audio
+            | Instruction::Pitch(_) => return Chip8Variant::XoChip,
+            Instruction::Scd(_)
+            | Instruction::Scr
+            | Instruction::Scl
+            | Instruction::Exit
+            | Instruction::Low
+            | Instruction::High
+            | Instruction::LdHf(_)
+            | Instruction::LdRVx(_)
+            | Instruction::LdVxR(_) => super_chip = true,
+            _ => {}
+        }
+    }
+
+    if super_chip {
+        Chip8Variant::SuperChip
+    } else {
+        Chip8Variant::Chip8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_chip8() {
+        // LD V0, 0x01 ; DRW V0, V0, 5 ; JP 0x204
+        assert_eq!(
+            detect_profile(&[0x60, 0x01, 0xd0, 0x05, 0x12, 0x04]),
+            Quirks::CHIP8
+        );
+        assert_eq!(detect_profile(&[]), Quirks::CHIP8);
+    }
+
+    #[test]
+    fn detect_super_chip() {
+        // HIGH ; SCR ; EXIT
+        assert_eq!(
+            detect_profile(&[0x00, 0xff, 0x00, 0xfb, 0x00, 0xfd]),
+            Quirks::SUPER_CHIP
+        );
+        // LD V0, 0x01 ; LD R, V3
+        assert_eq!(
+            detect_profile(&[0x60, 0x01, 0xf3, 0x75]),
+            Quirks::SUPER_CHIP
+        );
+    }
+
+    #[test]
+    fn detect_xo_chip() {
+        // HIGH ; LD I, long 0x1234 ; PLANE 3
+        assert_eq!(
+            detect_profile(&[0x00, 0xff, 0xf0, 0x00, 0x12, 0x34, 0xf3, 0x01]),
+            Quirks::XO_CHIP
+        );
+        // SCU 4
+        assert_eq!(detect_profile(&[0x00, 0xd4]), Quirks::XO_CHIP);
+    }
+
+    #[test]
+    fn detect_variants() {
+        let roms: [(&[u8], Chip8Variant); 6] = [
+            (&[], Chip8Variant::Chip8),
+            (&[0x60, 0x01, 0xd0, 0x05], Chip8Variant::Chip8), // LD V0, 0x01 ; DRW V0, V0, 5
+            (&[0x00, 0xff], Chip8Variant::SuperChip),         // HIGH
+            (&[0xf3, 0x85], Chip8Variant::SuperChip),         // LD V3, R
+            (&[0x00, 0xfd, 0xf0, 0x02], Chip8Variant::XoChip), // EXIT ; AUDIO
+            (&[0xf0, 0x00, 0x12, 0x34], Chip8Variant::XoChip), // LD I, long 0x1234
+        ];
+
+        for (rom, variant) in roms.iter() {
+            assert_eq!(detect_variant(rom), *variant);
+            assert_eq!(detect_profile(rom), variant.quirks());
+        }
+
+        assert_eq!(Chip8Variant::XoChip.to_string(), "XO-CHIP");
+        assert_eq!(Chip8Variant::SuperChip.to_string(), "SUPER-CHIP");
+        assert_eq!(Chip8Variant::Chip8.to_string(), "CHIP-8");
+    }
+}
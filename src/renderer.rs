@@ -0,0 +1,102 @@
+use crate::display::Display;
+use alloc::string::String;
+
+// A host backend presenting the display, usually called once after every VM::run_frame
+pub trait Renderer {
+    fn draw(&mut self, display: &Display);
+}
+
+pub struct NullRenderer;
+
+impl Renderer for NullRenderer {
+    fn draw(&mut self, _display: &Display) {}
+}
+
+// Keeps the last drawn frame as text, '#' for lit pixels and '.' for unlit ones
+#[derive(Default)]
+pub struct AsciiRenderer {
+    pub frame: String,
+}
+
+impl AsciiRenderer {
+    pub fn new() -> AsciiRenderer {
+        AsciiRenderer::default()
+    }
+}
+
+impl Renderer for AsciiRenderer {
+    fn draw(&mut self, display: &Display) {
+        self.frame = display.to_ascii_with('#', '.');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::VM;
+
+    #[derive(Default)]
+    struct MockRenderer {
+        draw_calls: usize,
+        lit_pixels: usize,
+    }
+
+    impl Renderer for MockRenderer {
+        fn draw(&mut self, display: &Display) {
+            self.draw_calls += 1;
+            self.lit_pixels = 0;
+
+            for y in 0..display.height() {
+                for x in 0..display.width() {
+                    if display.pixel(x, y) {
+                        self.lit_pixels += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mock_renderer() {
+        let mut vm = VM::new();
+        let mut renderer = MockRenderer::default();
+
+        vm.write_u16(0x200, 0xa050); // LD I, 0x050
+        vm.write_u16(0x202, 0xd005); // DRW V0, V0, 5
+        vm.write_u16(0x204, 0x1204); // JP 0x204
+
+        for _ in 0..3 {
+            vm.run_frame(2);
+            renderer.draw(&vm.display);
+        }
+
+        assert_eq!(renderer.draw_calls, 3);
+        assert_eq!(renderer.lit_pixels, 14);
+    }
+
+    #[test]
+    fn null_renderer() {
+        let vm = VM::new();
+        let mut renderer = NullRenderer;
+
+        renderer.draw(&vm.display);
+    }
+
+    #[test]
+    fn ascii_renderer() {
+        let mut display = Display::new();
+        let mut renderer = AsciiRenderer::new();
+
+        display.set_pixel(0, 0, true);
+        display.set_pixel(63, 31, true);
+        renderer.draw(&display);
+
+        let lines: Vec<&str> = renderer.frame.lines().collect();
+
+        assert_eq!(lines.len(), 32);
+        assert!(lines.iter().all(|line| line.len() == 64));
+        assert_eq!(&lines[0][..2], "#.");
+        assert_eq!(&lines[31][62..], ".#");
+        assert_eq!(renderer.frame.matches('#').count(), 2);
+    }
+}
@@ -0,0 +1,439 @@
+use crate::display::{Resolution, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH};
+use crate::error::VmError;
+use crate::vm::{AUDIO_BUFFER_SIZE, INITIAL_PC, RPL_SIZE, V_REG_SIZE};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Bump whenever the saved fields or their encoding change
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+const MAGIC: [u8; 4] = *b"DALE";
+
+// Everything needed to resume a ROM exactly where it was, configuration like quirks and host
+// state like the keypad or breakpoints is left out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmSnapshot {
+    pub version: u16,
+    pub memory: Vec<u8>,
+    pub stack: Vec<u16>,
+    pub v: [u8; V_REG_SIZE],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub resolution: Resolution,
+    pub selected_planes: u8,
+    pub pixels: Vec<u8>,
+    pub rpl: [u8; RPL_SIZE],
+    pub halted: bool,
+    pub cycles: u64,
+    pub entry: u16,
+    pub rom_start: usize,
+    pub rom_size: usize,
+    pub audio_buffer: [u8; AUDIO_BUFFER_SIZE],
+    pub pitch: u8,
+    pub rng_seed: [u8; 32],
+    pub rng_stream: u64,
+    pub rng_word_pos: u128,
+}
+
+// One difference reported by VmSnapshot::diff, left is self and right the other snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateDelta {
+    V { index: u8, left: u8, right: u8 },
+    I { left: u16, right: u16 },
+    Pc { left: u16, right: u16 },
+    Sp { left: u16, right: u16 },
+    DelayTimer { left: u8, right: u8 },
+    SoundTimer { left: u8, right: u8 },
+    Stack { index: usize, left: u16, right: u16 },
+    // Differing bytes in start..end, bytes only one of the memories has count as differing
+    Memory { start: usize, end: usize },
+    Resolution { left: Resolution, right: Resolution },
+    // Number of pixels with a different set of lit planes, only compared at equal resolutions
+    Pixels { count: usize },
+    Cycles { left: u64, right: u64 },
+}
+
+impl VmSnapshot {
+    pub fn diff(&self, other: &VmSnapshot) -> Vec<StateDelta> {
+        let mut deltas = Vec::new();
+
+        for (index, (&left, &right)) in self.v.iter().zip(other.v.iter()).enumerate() {
+            if left != right {
+                deltas.push(StateDelta::V {
+                    index: index as u8,
+                    left,
+                    right,
+                });
+            }
+        }
+
+        if self.i != other.i {
+            deltas.push(StateDelta::I {
+                left: self.i,
+                right: other.i,
+            });
+        }
+
+        if self.pc != other.pc {
+            deltas.push(StateDelta::Pc {
+                left: self.pc,
+                right: other.pc,
+            });
+        }
+
+        if self.sp != other.sp {
+            deltas.push(StateDelta::Sp {
+                left: self.sp,
+                right: other.sp,
+            });
+        }
+
+        if self.delay_timer != other.delay_timer {
+            deltas.push(StateDelta::DelayTimer {
+                left: self.delay_timer,
+                right: other.delay_timer,
+            });
+        }
+
+        if self.sound_timer != other.sound_timer {
+            deltas.push(StateDelta::SoundTimer {
+                left: self.sound_timer,
+                right: other.sound_timer,
+            });
+        }
+
+        for (index, (&left, &right)) in self.stack.iter().zip(other.stack.iter()).enumerate() {
+            if left != right {
+                deltas.push(StateDelta::Stack { index, left, right });
+            }
+        }
+
+        let len = self.memory.len().max(other.memory.len());
+        let mut start = None;
+
+        for address in 0..=len {
+            let differs = address < len && self.memory.get(address) != other.memory.get(address);
+
+            match (start, differs) {
+                (None, true) => start = Some(address),
+                (Some(from), false) => {
+                    deltas.push(StateDelta::Memory {
+                        start: from,
+                        end: address,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        if self.resolution != other.resolution {
+            deltas.push(StateDelta::Resolution {
+                left: self.resolution,
+                right: other.resolution,
+            });
+        } else {
+            let count = self
+                .pixels
+                .iter()
+                .zip(other.pixels.iter())
+                .filter(|(left, right)| left != right)
+                .count();
+
+            if count > 0 {
+                deltas.push(StateDelta::Pixels { count });
+            }
+        }
+
+        if self.cycles != other.cycles {
+            deltas.push(StateDelta::Cycles {
+                left: self.cycles,
+                right: other.cycles,
+            });
+        }
+
+        deltas
+    }
+
+    // Little endian, prefixed with a magic and the version so older or newer data gets rejected
+    // before any of it is interpreted
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.memory.len() + self.pixels.len() + 256);
+
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(&(self.stack.len() as u16).to_le_bytes());
+        for address in &self.stack {
+            bytes.extend_from_slice(&address.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.v);
+        bytes.extend_from_slice(&self.i.to_le_bytes());
+        bytes.extend_from_slice(&self.pc.to_le_bytes());
+        bytes.extend_from_slice(&self.sp.to_le_bytes());
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.push(match self.resolution {
+            Resolution::Lores => 0,
+            Resolution::Hires => 1,
+        });
+        bytes.push(self.selected_planes);
+        bytes.extend_from_slice(&self.pixels);
+        bytes.extend_from_slice(&self.rpl);
+        bytes.push(self.halted as u8);
+        bytes.extend_from_slice(&self.cycles.to_le_bytes());
+        bytes.extend_from_slice(&self.entry.to_le_bytes());
+        bytes.extend_from_slice(&(self.rom_start as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.rom_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.audio_buffer);
+        bytes.push(self.pitch);
+        bytes.extend_from_slice(&self.rng_seed);
+        bytes.extend_from_slice(&self.rng_stream.to_le_bytes());
+        bytes.extend_from_slice(&self.rng_word_pos.to_le_bytes());
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<VmSnapshot, VmError> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(VmError::InvalidSnapshot);
+        }
+
+        let version = reader.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(VmError::VersionMismatch {
+                expected: SNAPSHOT_VERSION,
+                found: version,
+            });
+        }
+
+        let memory_size = reader.u32()? as usize;
+        if memory_size <= INITIAL_PC as usize {
+            return Err(VmError::InvalidSnapshot);
+        }
+        let memory = reader.take(memory_size)?.to_vec();
+
+        let stack_size = reader.u16()? as usize;
+        if stack_size == 0 {
+            return Err(VmError::InvalidSnapshot);
+        }
+        let mut stack = vec![0; stack_size];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+
+        let v = reader.array()?;
+        let i = reader.u16()?;
+        let pc = reader.u16()?;
+        let sp = reader.u16()?;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let (resolution, pixel_count) = match reader.u8()? {
+            0 => (Resolution::Lores, LORES_WIDTH * LORES_HEIGHT),
+            1 => (Resolution::Hires, HIRES_WIDTH * HIRES_HEIGHT),
+            _ => return Err(VmError::InvalidSnapshot),
+        };
+        let selected_planes = reader.u8()?;
+        let pixels = reader.take(pixel_count)?.to_vec();
+        let rpl = reader.array()?;
+        let halted = reader.u8()? != 0;
+        let cycles = u64::from_le_bytes(reader.array()?);
+        let entry = reader.u16()?;
+        let rom_start = reader.u32()? as usize;
+        let rom_size = reader.u32()? as usize;
+        let audio_buffer = reader.array()?;
+        let pitch = reader.u8()?;
+        let rng_seed = reader.array()?;
+        let rng_stream = u64::from_le_bytes(reader.array()?);
+        let rng_word_pos = u128::from_le_bytes(reader.array()?);
+
+        if !reader.bytes.is_empty()
+            || entry as usize >= memory_size
+            || rom_start
+                .checked_add(rom_size)
+                .map_or(true, |end| end > memory_size)
+        {
+            return Err(VmError::InvalidSnapshot);
+        }
+
+        Ok(VmSnapshot {
+            version,
+            memory,
+            stack,
+            v,
+            i,
+            pc,
+            sp,
+            delay_timer,
+            sound_timer,
+            resolution,
+            selected_planes,
+            pixels,
+            rpl,
+            halted,
+            cycles,
+            entry,
+            rom_start,
+            rom_size,
+            audio_buffer,
+            pitch,
+            rng_seed,
+            rng_stream,
+            rng_word_pos,
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], VmError> {
+        if self.bytes.len() < len {
+            return Err(VmError::InvalidSnapshot);
+        }
+
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], VmError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, VmError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, VmError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, VmError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::VM;
+
+    #[test]
+    fn round_trip() {
+        let mut vm = VM::with_seed(7);
+
+        vm.load_rom_bytes(&[0xc0, 0xff, 0x00, 0xff, 0xd0, 0x05, 0x12, 0x06])
+            .unwrap();
+        vm.run_until_halt(10);
+
+        let snapshot = vm.snapshot();
+
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(VmSnapshot::from_bytes(&snapshot.to_bytes()), Ok(snapshot));
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let mut vm = VM::new();
+        let mut snapshot = vm.snapshot();
+
+        snapshot.version = SNAPSHOT_VERSION + 1;
+
+        let bytes = snapshot.to_bytes();
+        let mismatch = VmError::VersionMismatch {
+            expected: SNAPSHOT_VERSION,
+            found: SNAPSHOT_VERSION + 1,
+        };
+
+        assert_eq!(VmSnapshot::from_bytes(&bytes), Err(mismatch));
+
+        vm.regs.v[3] = 0x42;
+
+        assert_eq!(vm.restore(&snapshot), Err(mismatch));
+        assert_eq!(vm.regs.v[3], 0x42);
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        let bytes = VM::new().snapshot().to_bytes();
+
+        assert_eq!(VmSnapshot::from_bytes(&[]), Err(VmError::InvalidSnapshot));
+        assert_eq!(
+            VmSnapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(VmError::InvalidSnapshot)
+        );
+        assert_eq!(
+            VmSnapshot::from_bytes(&[b"CHIP".as_slice(), &bytes[4..]].concat()),
+            Err(VmError::InvalidSnapshot)
+        );
+
+        // Would wrap around to a valid range where usize is 32 bits
+        let mut snapshot = VM::new().snapshot();
+        snapshot.rom_start = u32::MAX as usize;
+        snapshot.rom_size = 2;
+
+        assert_eq!(
+            VmSnapshot::from_bytes(&snapshot.to_bytes()),
+            Err(VmError::InvalidSnapshot)
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let mut left = VM::new();
+        let mut right = VM::new();
+
+        left.process_opcode(0x6105); // LD V1, 0x05
+        left.process_opcode(0xa300); // LD I, 0x300
+        left.process_opcode(0xf155); // LD [I], V1
+        right.process_opcode(0x6107); // LD V1, 0x07
+        right.process_opcode(0x6203); // LD V2, 0x03
+        right.process_opcode(0xa300); // LD I, 0x300
+
+        assert_eq!(left.snapshot().diff(&left.snapshot()), []);
+        assert_eq!(
+            left.snapshot().diff(&right.snapshot()),
+            [
+                StateDelta::V {
+                    index: 1,
+                    left: 0x05,
+                    right: 0x07
+                },
+                StateDelta::V {
+                    index: 2,
+                    left: 0x00,
+                    right: 0x03
+                },
+                StateDelta::Memory {
+                    start: 0x301,
+                    end: 0x302
+                },
+            ]
+        );
+
+        right.process_opcode(0xf129); // LD F, V1
+        right.process_opcode(0xd125); // DRW V1, V2, 5
+
+        let deltas = left.snapshot().diff(&right.snapshot());
+
+        assert!(deltas.contains(&StateDelta::Pc {
+            left: INITIAL_PC + 6,
+            right: INITIAL_PC + 10
+        }));
+        assert!(deltas.contains(&StateDelta::Pixels { count: 8 }));
+        assert!(deltas.contains(&StateDelta::Cycles { left: 3, right: 5 }));
+    }
+}
@@ -1,10 +1,159 @@
+use crate::bus::Bus;
+use crate::clock::Clock;
+use crate::disassembler;
+use crate::display::{
+    Display, Palette, Resolution, ALL_PLANES, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH,
+    MAX_PLANES,
+};
+use crate::error::VmError;
+use crate::font::*;
+use crate::instruction::Instruction;
+use crate::keypad::{KeySource, Keypad};
+use crate::quirks::Quirks;
+use crate::snapshot::{VmSnapshot, SNAPSHOT_VERSION};
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::time::Duration;
+use log::trace;
 use rand::{Rng, SeedableRng};
 
 pub const MEMORY_SIZE: usize = 4096;
+pub const XO_MEMORY_SIZE: usize = 65536;
 pub const STACK_SIZE: usize = 16;
 pub const V_REG_SIZE: usize = 16;
 pub const INITIAL_PC: u16 = 0x200;
+pub const OPCODE_CLASSES: usize = 16;
+pub const RPL_SIZE: usize = 8;
+pub const AUDIO_BUFFER_SIZE: usize = 16;
+pub const DEFAULT_PITCH: u8 = 64;
+// Roughly 700 instructions per second at 60 Hz
+pub const DEFAULT_CYCLES_PER_FRAME: usize = 11;
+// COSMAC VIP machine cycles per 60 Hz frame, see Instruction::vip_cycles
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+pub const TIMER_HZ: u32 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Halted,
+    SelfJump,
+    Fault(VmError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepStatus {
+    Ok,
+    Halted,
+    BreakpointHit,
+    WaitingForKey,
+    Fault(VmError),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepResult {
+    // None when nothing was executed, the VM is stopped or pc is on a breakpoint
+    pub instruction: Option<Instruction>,
+    pub pc: u16,
+    pub status: StepStatus,
+    // Disassembly of the executed instruction, only formatted while trace_mnemonics is set
+    pub mnemonic: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunResult {
+    Halted,
+    SelfJump,
+    CycleLimitReached,
+    BreakpointHit,
+    Drawn,
+    Fault(VmError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemAccess {
+    pub address: usize,
+    pub value: u8,
+    pub kind: AccessKind,
+}
+
+pub type MemoryHook = Box<dyn FnMut(MemAccess) + Send>;
+
+// A RefCell so read_u8 can stay &self, hooks are not carried over by VM::clone
+#[derive(Default)]
+struct MemoryHookSlot(RefCell<Option<MemoryHook>>);
+
+impl Clone for MemoryHookSlot {
+    fn clone(&self) -> Self {
+        MemoryHookSlot::default()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VmEvent {
+    Cls,
+    Drw { x: u8, y: u8, collision: bool },
+    BeepStart,
+    BeepStop,
+    Halt,
+    StackPush(u16),
+    StackPop(u16),
+}
+
+pub type EventHook = Box<dyn FnMut(VmEvent) + Send>;
+
+// Like the memory hook, listeners are not carried over by VM::clone
+#[derive(Default)]
+struct EventHookSlot(Option<EventHook>);
+
+impl Clone for EventHookSlot {
+    fn clone(&self) -> Self {
+        EventHookSlot::default()
+    }
+}
+
+// Attached buses are not carried over by VM::clone either, the clone reads `memory` again
+#[derive(Default)]
+struct BusSlot(Option<Box<dyn Bus + Send>>);
+
+impl Clone for BusSlot {
+    fn clone(&self) -> Self {
+        BusSlot::default()
+    }
+}
+
+// Neither are key sources, the clone keeps the keypad state it was cloned with
+#[derive(Default)]
+struct KeySourceSlot(Option<Box<dyn KeySource + Send>>);
+
+impl Clone for KeySourceSlot {
+    fn clone(&self) -> Self {
+        KeySourceSlot::default()
+    }
+}
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomInfo {
+    pub size: usize,
+    pub first_opcode: u16,
+    // The ROM fits in memory at the entry, load_rom_bytes fails with RomTooLarge otherwise
+    pub fits: bool,
+    // Bytes of memory left after the ROM, at the entry
+    pub free: usize,
+}
+
+#[derive(Clone)]
 pub struct Registers {
     pub v: [u8; V_REG_SIZE],
     pub i: u16,
@@ -14,11 +163,69 @@ pub struct Registers {
     pub sound_timer: u8,
 }
 
+#[derive(Clone)]
 pub struct VM {
-    pub memory: [u8; MEMORY_SIZE],
-    pub stack: [u16; STACK_SIZE],
+    pub memory: Vec<u8>,
+    pub stack: Vec<u16>,
     pub regs: Registers,
+    pub display: Display,
+    pub keypad: Keypad,
+    pub quirks: Quirks,
+    frame_drawn: bool,
+    pub rpl: [u8; RPL_SIZE],
+    pub keep_rpl_on_reset: bool,
+    // Fault on SYS addr instead of skipping it, there is no machine code to call into
+    pub strict_sys: bool,
+    // Fault instead of executing from an odd pc
+    pub strict_alignment: bool,
+    // Fault on ROM writes below INITIAL_PC, where the fonts live, host writes are not affected
+    pub protect_interpreter_area: bool,
+    // Fault on ROM writes running past the end of memory instead of wrapping around to 0
+    pub strict_bounds: bool,
+    // Timer ticks a pending LD Vx, K waits before giving up, None waits forever
+    pub key_wait_timeout: Option<u32>,
+    // Key stored by a timed out LD Vx, K, None faults with KeyWaitTimeout instead
+    pub key_wait_default: Option<u8>,
+    halted: bool,
+    fault: Option<VmError>,
+    self_jump: Option<u16>,
+    waiting_for_key: Option<u8>,
+    key_wait_ticks: u32,
+    breakpoints: BTreeSet<u16>,
+    resume_breakpoint: Option<u16>,
+    cycles: u64,
+    // Machine cycles run_frame_timed went over its last budget by
+    cycle_debt: u32,
+    timer_clock: Clock,
+    entry: u16,
+    rom_start: usize,
+    rom_size: usize,
+    memory_hook: MemoryHookSlot,
+    // Set by fast_forward to skip the per-opcode trace log
+    quiet: bool,
+    event_hook: EventHookSlot,
+    bus: BusSlot,
+    key_source: KeySourceSlot,
+    font: [u8; FONT.len()],
+    pub audio_buffer: [u8; AUDIO_BUFFER_SIZE],
+    pub pitch: u8,
     pub random: rand_chacha::ChaCha8Rng,
+    pub opcode_stats_enabled: bool,
+    pub cycles_per_frame: usize,
+    pub trace_mnemonics: bool,
+    opcode_stats: [u64; OPCODE_CLASSES],
+    opcode_coverage: BTreeSet<u16>,
+    decode_cache: Option<Vec<Option<(u16, Instruction)>>>,
+    // Addresses the traced disassembly found instructions at, see enable_data_guard
+    code_map: Option<Vec<bool>>,
+}
+
+// Starts half a tick in, so frame lengths rounded down to whole nanoseconds (1/60 s,
+// 1/30 s) still add up to the expected number of ticks
+fn timer_clock() -> Clock {
+    let mut clock = Clock::new(TIMER_HZ);
+    clock.cycles_for_elapsed(Duration::from_nanos(500_000_000 / TIMER_HZ as u64));
+    clock
 }
 
 impl Registers {
@@ -32,11 +239,92 @@ impl Registers {
     }
 }
 
+// One line dump of the CPU state, e.g. "PC=0x0204 I=0x0300 SP=16 V=[12 00 ... 00] DT=00 ST=00"
+impl fmt::Display for VM {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PC={:#06x} I={:#06x} SP={} V=[",
+            self.regs.pc, self.regs.i, self.regs.sp
+        )?;
+
+        for (x, value) in self.regs.v.iter().enumerate() {
+            if x > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02x}", value)?;
+        }
+
+        write!(
+            f,
+            "] DT={:02x} ST={:02x}",
+            self.regs.delay_timer, self.regs.sound_timer
+        )
+    }
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        VM::new()
+    }
+}
+
 impl VM {
     pub fn new() -> VM {
-        VM {
-            memory: [0; MEMORY_SIZE],
-            stack: [0; STACK_SIZE],
+        VM::with_memory_size(MEMORY_SIZE)
+    }
+
+    // Seeds the ChaCha8 generator behind RND, VM::new uses seed 0
+    pub fn with_seed(seed: u64) -> VM {
+        let mut vm = VM::new();
+        vm.random = rand_chacha::ChaCha8Rng::seed_from_u64(seed);
+        vm
+    }
+
+    // For interpreters starting programs somewhere else than INITIAL_PC, e.g. 0x600 on the
+    // ETI-660, reset and load_rom use the entry from then on
+    pub fn with_entry(mut self, entry: u16) -> Result<VM, VmError> {
+        self.set_entry(entry)?;
+        Ok(self)
+    }
+
+    pub fn entry(&self) -> u16 {
+        self.entry
+    }
+
+    pub fn set_entry(&mut self, entry: u16) -> Result<(), VmError> {
+        if entry as usize >= self.memory.len() {
+            return Err(VmError::InvalidEntry(entry));
+        }
+
+        self.entry = entry;
+        self.regs.pc = entry;
+
+        Ok(())
+    }
+
+    // Deeper (or shallower) than the STACK_SIZE levels of the original interpreter, CALL still
+    // faults with StackOverflow once all of them are used
+    pub fn with_stack_size(mut self, size: usize) -> Result<VM, VmError> {
+        if size == 0 || size > u16::MAX as usize {
+            return Err(VmError::InvalidStackSize(size));
+        }
+
+        self.stack = vec![0; size];
+        self.regs.sp = size as u16;
+        Ok(self)
+    }
+
+    pub fn with_memory_size(size: usize) -> VM {
+        assert!(
+            size > INITIAL_PC as usize,
+            "Memory size {:#x} leaves no room for a program",
+            size
+        );
+
+        let mut vm = VM {
+            memory: vec![0; size],
+            stack: vec![0; STACK_SIZE],
             regs: Registers {
                 v: [0; V_REG_SIZE],
                 i: 0,
@@ -45,1025 +333,5884 @@ impl VM {
                 delay_timer: 0,
                 sound_timer: 0,
             },
+            display: Display::new(),
+            keypad: Keypad::new(),
+            quirks: Quirks::default(),
+            frame_drawn: false,
+            rpl: [0; RPL_SIZE],
+            keep_rpl_on_reset: false,
+            strict_sys: false,
+            strict_alignment: false,
+            protect_interpreter_area: false,
+            strict_bounds: false,
+            key_wait_timeout: None,
+            key_wait_default: None,
+            halted: false,
+            fault: None,
+            self_jump: None,
+            waiting_for_key: None,
+            key_wait_ticks: 0,
+            breakpoints: BTreeSet::new(),
+            resume_breakpoint: None,
+            cycles: 0,
+            cycle_debt: 0,
+            timer_clock: timer_clock(),
+            entry: INITIAL_PC,
+            rom_start: INITIAL_PC as usize,
+            rom_size: 0,
+            memory_hook: MemoryHookSlot::default(),
+            quiet: false,
+            event_hook: EventHookSlot::default(),
+            bus: BusSlot::default(),
+            key_source: KeySourceSlot::default(),
+            font: FONT,
+            audio_buffer: [0; AUDIO_BUFFER_SIZE],
+            pitch: DEFAULT_PITCH,
             random: rand_chacha::ChaCha8Rng::seed_from_u64(0),
-        }
+            opcode_stats_enabled: false,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            trace_mnemonics: false,
+            opcode_stats: [0; OPCODE_CLASSES],
+            opcode_coverage: BTreeSet::new(),
+            decode_cache: None,
+            code_map: None,
+        };
+        vm.load_fonts();
+        vm
     }
 
     pub fn reset(&mut self) {
         self.memory.fill(0);
-        self.stack.fill(0);
-        self.regs.reset();
+        self.rom_size = 0;
+        self.reset_keeping_rom();
     }
 
-    pub fn read_u16(&self, address: usize) -> u16 {
-        ((self.memory[address] as u16) << 8) | self.memory[address + 1] as u16
+    // Cold start, same as reset, the ROM has to be loaded again
+    pub fn power_on_reset(&mut self) {
+        self.reset();
     }
 
-    pub fn write_u16(&mut self, address: usize, value: u16) {
-        self.memory[address] = (value >> 8) as u8;
-        self.memory[address + 1] = (value & 0xff) as u8;
+    // Warm start, same as reset_keeping_rom, the ROM and font stay while CPU, stack, timers and
+    // display start over
+    pub fn soft_reset(&mut self) {
+        self.reset_keeping_rom();
     }
 
-    pub fn read_u8(&self, address: usize) -> u8 {
-        self.memory[address]
-    }
+    // Clears every plane regardless of the selected ones, the selection is kept
+    pub fn clear_display(&mut self) {
+        let selected = self.display.selected_planes();
 
-    pub fn write_u8(&mut self, address: usize, value: u8) {
-        self.memory[address] = value;
+        self.display.select_planes(ALL_PLANES);
+        self.display.clear();
+        self.display.select_planes(selected);
     }
 
-    pub fn load_rom(&mut self, filename: &str) {
-        let bytes = std::fs::read(filename).unwrap();
+    pub fn clear_timers(&mut self) {
+        self.regs.delay_timer = 0;
+        self.set_sound_timer(0);
+    }
 
-        self.reset();
+    // Same as reset but leaves memory from INITIAL_PC (or a lower entry) onward and the loaded
+    // ROM wherever it was placed untouched, restarting the ROM
+    pub fn reset_keeping_rom(&mut self) {
+        let rom = self.rom_start..self.rom_start + self.rom_size;
+        let rom_bytes = self.memory[rom.clone()].to_vec();
 
-        let pc = self.regs.pc as usize;
-        for i in 0..bytes.len() {
-            self.memory[pc + i] = bytes[i];
+        self.memory[..INITIAL_PC.min(self.entry) as usize].fill(0);
+        self.load_fonts();
+        self.memory[rom].copy_from_slice(&rom_bytes);
+        self.stack.fill(0);
+        self.regs.reset();
+        self.regs.sp = self.stack.len() as u16;
+        self.regs.pc = self.entry;
+        self.halted = false;
+        self.fault = None;
+        self.self_jump = None;
+        self.waiting_for_key = None;
+        self.key_wait_ticks = 0;
+        self.resume_breakpoint = None;
+        self.cycles = 0;
+        self.cycle_debt = 0;
+        self.timer_clock = timer_clock();
+        self.frame_drawn = false;
+        self.audio_buffer.fill(0);
+        self.pitch = DEFAULT_PITCH;
+        self.display.reset();
+        if !self.keep_rpl_on_reset {
+            self.rpl.fill(0);
         }
+        self.reset_opcode_stats();
+        self.invalidate_decode_cache();
     }
 
-    pub fn step(&mut self) {
-        let opcode = self.read_u16(self.regs.pc as usize);
-        self.process_opcode(opcode);
+    fn load_fonts(&mut self) {
+        self.memory[FONT_START..FONT_START + FONT.len()].copy_from_slice(&self.font);
+        self.memory[BIG_FONT_START..BIG_FONT_START + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
     }
 
-    pub fn process_opcode(&mut self, opcode: u16) {
-        let op = (opcode >> 12) as u8;
-        // println!("opcode: {:#06x} op: {:#04x}", opcode, op);
-
-        match op {
-            0x0 => self.process_opcode_0(opcode),
-            0x1 => self.process_opcode_1nnn(opcode),
-            0x2 => self.process_opcode_2nnn(opcode),
-            0x3 => self.process_opcode_3xkk(opcode),
-            0x4 => self.process_opcode_4xkk(opcode),
-            0x5 => self.process_opcode_5xy0(opcode),
-            0x6 => self.process_opcode_6xkk(opcode),
-            0x7 => self.process_opcode_7xkk(opcode),
-            0x8 => self.process_opcode_8(opcode),
-            0x9 => self.process_opcode_9xy0(opcode),
-            0xa => self.process_opcode_annn(opcode),
-            0xb => self.process_opcode_bnnn(opcode),
-            0xc => self.process_opcode_cxkk(opcode),
-            0xd => self.process_opcode_dxyn(opcode),
-            0xe => self.process_opcode_e(opcode),
-            0xf => self.process_opcode_f(opcode),
-            _ => panic!("Invalid opcode {:#06x}", opcode),
-        }
-    }
-
-    fn process_opcode_0(&mut self, opcode: u16) {
-        let value = opcode & 0x0fff;
-        match value {
-            0x00ee => self.process_opcode_00ee(),
-            0x00e0 => self.process_opcode_00e0(),
-            _ => self.process_opcode_0nnn(value),
-        }
+    // Replaces the small font at FONT_START, kept across resets, 5 bytes per digit 0-F
+    pub fn set_font(&mut self, font: &[u8; FONT.len()]) {
+        self.font = *font;
+        self.memory[FONT_START..FONT_START + FONT.len()].copy_from_slice(font);
+        self.invalidate_decode_cache();
     }
 
-    // CLS
-    fn process_opcode_00e0(&mut self) {
-        unimplemented!("opcode_00e0");
+    // Colors used by Display::to_rgba, kept across resets like the font
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.display.set_palette(palette);
     }
 
-    // RET
-    fn process_opcode_00ee(&mut self) {
-        self.regs.pc = self.stack[self.regs.sp as usize];
-        self.stack[self.regs.sp as usize] = 0;
-        self.regs.sp += 1;
+    // Number of executed opcodes per class (high nibble), counted only while
+    // opcode_stats_enabled is set
+    pub fn opcode_stats(&self) -> &[u64; OPCODE_CLASSES] {
+        &self.opcode_stats
     }
 
-    // SYS addr
-    fn process_opcode_0nnn(&mut self, _opcode: u16) {
-        unimplemented!("opcode_0nnn");
+    // Distinct instruction patterns executed, see Instruction::pattern, also collected only
+    // while opcode_stats_enabled is set
+    pub fn opcode_coverage(&self) -> &BTreeSet<u16> {
+        &self.opcode_coverage
     }
 
-    // JP addr
-    fn process_opcode_1nnn(&mut self, opcode: u16) {
-        self.regs.pc = opcode & 0x0fff;
+    pub fn reset_opcode_stats(&mut self) {
+        self.opcode_stats.fill(0);
+        self.opcode_coverage.clear();
     }
 
-    // CALL addr
-    fn process_opcode_2nnn(&mut self, opcode: u16) {
-        self.regs.pc += 2;
-        self.regs.sp -= 1;
-        self.stack[self.regs.sp as usize] = self.regs.pc;
-        self.regs.pc = opcode & 0x0fff;
+    // Only the low nibble of x selects the register, like the x and y fields of an opcode
+    pub fn reg(&self, x: u8) -> u8 {
+        self.regs.v[(x & 0x0f) as usize]
     }
 
-    // SE Vx, byte
-    fn process_opcode_3xkk(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let kk: u8 = (opcode & 0x00ff) as u8;
+    pub fn set_reg(&mut self, x: u8, value: u8) {
+        self.regs.v[(x & 0x0f) as usize] = value;
+    }
 
-        self.regs.pc += 2;
+    pub fn v_registers(&self) -> &[u8; V_REG_SIZE] {
+        &self.regs.v
+    }
 
-        if self.regs.v[x as usize] == kk {
-            self.regs.pc += 2;
-        }
+    pub fn pc(&self) -> u16 {
+        self.regs.pc
     }
 
-    // SNE Vx, byte
-    fn process_opcode_4xkk(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let kk: u8 = (opcode & 0x00ff) as u8;
+    pub fn set_pc(&mut self, pc: u16) {
+        self.regs.pc = pc;
+    }
 
-        self.regs.pc += 2;
+    pub fn i(&self) -> u16 {
+        self.regs.i
+    }
 
-        if self.regs.v[x as usize] != kk {
-            self.regs.pc += 2;
-        }
+    pub fn set_i(&mut self, i: u16) {
+        self.regs.i = i;
     }
 
-    // SE Vx, Vy
-    fn process_opcode_5xy0(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let y: u8 = ((opcode >> 4) & 0x000f) as u8;
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
 
-        self.regs.pc += 2;
+    // Addresses wrap around the end of memory
+    pub fn read_u16(&self, address: usize) -> u16 {
+        ((self.read_u8(address) as u16) << 8) | self.read_u8(address + 1) as u16
+    }
 
-        if self.regs.v[x as usize] == self.regs.v[y as usize] {
-            self.regs.pc += 2;
-        }
+    pub fn write_u16(&mut self, address: usize, value: u16) {
+        self.write_u8(address, (value >> 8) as u8);
+        self.write_u8(address + 1, (value & 0xff) as u8);
     }
 
-    // LD Vx, byte
-    fn process_opcode_6xkk(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let kk: u8 = (opcode & 0x00ff) as u8;
+    // Caches decoded instructions by pc, writes through write_u8/write_u16 invalidate
+    // the affected entries while direct writes to `memory` need invalidate_decode_cache
+    pub fn set_decode_cache_enabled(&mut self, enabled: bool) {
+        self.decode_cache = if enabled {
+            Some(vec![None; self.memory.len()])
+        } else {
+            None
+        };
+    }
 
-        self.regs.pc += 2;
-        self.regs.v[x as usize] = kk;
+    pub fn is_decode_cache_enabled(&self) -> bool {
+        self.decode_cache.is_some()
     }
 
-    // ADD Vx, byte
-    fn process_opcode_7xkk(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let kk: u8 = (opcode & 0x00ff) as u8;
+    pub fn invalidate_decode_cache(&mut self) {
+        if let Some(cache) = &mut self.decode_cache {
+            cache.fill(None);
+        }
+    }
 
-        self.regs.pc += 2;
-        self.regs.v[x as usize] += kk;
+    // Called on every read_u8/write_u8 with the wrapped address, direct `memory` accesses and
+    // fetches served by the decode cache are not reported
+    pub fn set_memory_hook(&mut self, hook: MemoryHook) {
+        *self.memory_hook.0.borrow_mut() = Some(hook);
     }
 
-    fn process_opcode_8(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let y: u8 = ((opcode >> 4) & 0x000f) as u8;
-        let op: u8 = (opcode & 0x000f) as u8;
+    pub fn clear_memory_hook(&mut self) {
+        *self.memory_hook.0.borrow_mut() = None;
+    }
 
-        match op {
-            0x0 => self.process_opcode_8xy0(x, y),
-            0x1 => self.process_opcode_8xy1(x, y),
-            0x2 => self.process_opcode_8xy2(x, y),
-            0x3 => self.process_opcode_8xy3(x, y),
-            0x4 => self.process_opcode_8xy4(x, y),
-            0x5 => self.process_opcode_8xy5(x, y),
-            0x6 => self.process_opcode_8xy6(x, y),
-            0x7 => self.process_opcode_8xy7(x, y),
-            0xe => self.process_opcode_8xye(x, y),
-            _ => panic!("Invalid opcode {:#06x}", opcode),
+    fn notify_memory_hook(&self, address: usize, value: u8, kind: AccessKind) {
+        if let Some(hook) = self.memory_hook.0.borrow_mut().as_mut() {
+            hook(MemAccess {
+                address,
+                value,
+                kind,
+            });
         }
     }
 
-    // LD Vx, Vy
-    fn process_opcode_8xy0(&mut self, x: u8, y: u8) {
-        self.regs.pc += 2;
-        self.regs.v[x as usize] = self.regs.v[y as usize];
+    // Replaces the previous listener, events are delivered in the order the VM produces them
+    pub fn on_event(&mut self, hook: EventHook) {
+        self.event_hook.0 = Some(hook);
     }
 
-    // OR Vx, Vy
-    fn process_opcode_8xy1(&mut self, x: u8, y: u8) {
-        self.regs.pc += 2;
-        self.regs.v[x as usize] = self.regs.v[x as usize] | self.regs.v[y as usize];
+    pub fn clear_event_hook(&mut self) {
+        self.event_hook.0 = None;
     }
 
-    // AND Vx, Vy
-    fn process_opcode_8xy2(&mut self, x: u8, y: u8) {
-        self.regs.pc += 2;
-        self.regs.v[x as usize] = self.regs.v[x as usize] & self.regs.v[y as usize];
+    fn emit(&mut self, event: VmEvent) {
+        if let Some(hook) = self.event_hook.0.as_mut() {
+            hook(event);
+        }
     }
 
-    // XOR Vx, Vy
-    fn process_opcode_8xy3(&mut self, x: u8, y: u8) {
-        self.regs.pc += 2;
-        self.regs.v[x as usize] = self.regs.v[x as usize] ^ self.regs.v[y as usize];
+    // Routes read_u8/write_u8, and so fetches and every handler access, to `bus` instead of
+    // `memory`, which ROM loading, resets and snapshots keep using
+    pub fn attach_bus(&mut self, bus: Box<dyn Bus + Send>) {
+        self.bus.0 = Some(bus);
+        self.invalidate_decode_cache();
     }
 
-    // ADD Vx, Vy
-    fn process_opcode_8xy4(&mut self, x: u8, y: u8) {
-        let value: u16 = self.regs.v[x as usize] as u16 + self.regs.v[y as usize] as u16;
+    pub fn detach_bus(&mut self) -> Option<Box<dyn Bus + Send>> {
+        self.invalidate_decode_cache();
+        self.bus.0.take()
+    }
 
-        self.regs.pc += 2;
-        self.regs.v[x as usize] = (value & 0x00ff) as u8;
-        self.regs.v[0xf] = if value > 255 { 1 } else { 0 };
+    // run_frame and run_frame_timed sync the keypad from `source` before running, keys set on
+    // the keypad directly are overwritten then
+    pub fn attach_key_source(&mut self, source: Box<dyn KeySource + Send>) {
+        self.key_source.0 = Some(source);
     }
 
-    // SUB Vx, Vy
-    fn process_opcode_8xy5(&mut self, x: u8, y: u8) {
-        self.regs.pc += 2;
-        self.regs.v[0xf] = if self.regs.v[x as usize] > self.regs.v[y as usize] {
-            1
-        } else {
-            0
-        };
-        self.regs.v[x as usize] =
-            (self.regs.v[x as usize] as i8 - self.regs.v[y as usize] as i8) as u8;
+    pub fn detach_key_source(&mut self) -> Option<Box<dyn KeySource + Send>> {
+        self.key_source.0.take()
     }
 
-    // SHR Vx {, Vy}
-    fn process_opcode_8xy6(&mut self, x: u8, _y: u8) {
-        self.regs.pc += 2;
-        self.regs.v[0xf] = self.regs.v[x as usize] & 1;
-        self.regs.v[x as usize] = self.regs.v[x as usize] >> 1;
+    fn poll_key_source(&mut self) {
+        if let Some(source) = &self.key_source.0 {
+            self.keypad.sync(source.as_ref());
+        }
     }
 
-    // SUBN Vx, Vy
-    fn process_opcode_8xy7(&mut self, x: u8, y: u8) {
-        self.regs.pc += 2;
-        self.regs.v[0xf] = if self.regs.v[y as usize] > self.regs.v[x as usize] {
-            1
-        } else {
-            0
-        };
-        self.regs.v[x as usize] =
-            (self.regs.v[y as usize] as i8 - self.regs.v[x as usize] as i8) as u8;
+    fn bus(&self) -> &dyn Bus {
+        match &self.bus.0 {
+            Some(bus) => bus.as_ref(),
+            None => &self.memory,
+        }
     }
 
-    // SHL Vx {, Vy}
-    fn process_opcode_8xye(&mut self, x: u8, _y: u8) {
-        self.regs.pc += 2;
-        self.regs.v[0xf] = self.regs.v[x as usize] & 1;
-        self.regs.v[x as usize] = self.regs.v[x as usize] << 1;
+    pub fn read_u8(&self, address: usize) -> u8 {
+        let address = address % self.memory.len();
+        let value = self.bus().read(address);
+
+        self.notify_memory_hook(address, value, AccessKind::Read);
+
+        value
     }
 
-    // SNE Vx, Vy
-    fn process_opcode_9xy0(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let y: u8 = ((opcode >> 4) & 0x000f) as u8;
+    // First protected address among the `len` bytes a handler is about to write at `address`
+    fn check_writable(&self, address: usize, len: usize) -> Result<(), VmError> {
+        if self.strict_bounds && address + len > self.memory.len() {
+            return Err(VmError::OutOfBounds { address, size: len });
+        }
 
-        self.regs.pc += 2;
+        if !self.protect_interpreter_area {
+            return Ok(());
+        }
+
+        let protected = INITIAL_PC.min(self.entry) as usize;
 
-        if self.regs.v[x as usize] != self.regs.v[y as usize] {
-            self.regs.pc += 2;
+        match (0..len)
+            .map(|offset| (address + offset) % self.memory.len())
+            .find(|&address| address < protected)
+        {
+            Some(address) => Err(VmError::WriteProtected(address as u16)),
+            None => Ok(()),
         }
     }
 
-    // LD I, addr
-    fn process_opcode_annn(&mut self, opcode: u16) {
-        self.regs.pc += 2;
-        self.regs.i = opcode & 0x0fff;
+    pub fn write_u8(&mut self, address: usize, value: u8) {
+        let size = self.memory.len();
+        let address = address % size;
+
+        match &mut self.bus.0 {
+            Some(bus) => bus.write(address, value),
+            None => self.memory.write(address, value),
+        }
+        self.notify_memory_hook(address, value, AccessKind::Write);
+
+        // The byte is either the first or the second half of a cached opcode
+        if let Some(cache) = &mut self.decode_cache {
+            cache[address] = None;
+            cache[(address + size - 1) % size] = None;
+        }
     }
 
-    // JP V0, addr
-    fn process_opcode_bnnn(&mut self, opcode: u16) {
-        self.regs.pc = self.regs.v[0] as u16 + (opcode & 0x0fff);
+    #[cfg(feature = "std")]
+    pub fn load_rom(&mut self, filename: &str) {
+        self.load_rom_at(filename, self.entry);
     }
 
-    // RND Vx, byte
-    fn process_opcode_cxkk(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let kk: u8 = (opcode & 0x00ff) as u8;
+    #[cfg(feature = "std")]
+    pub fn load_rom_at(&mut self, filename: &str, address: u16) {
+        let bytes = std::fs::read(filename).unwrap();
 
-        self.regs.pc += 2;
-        self.regs.v[x as usize] = self.random.gen::<u8>() & kk;
+        self.load_rom_bytes_at(&bytes, address).unwrap();
     }
 
-    // DRW Vx, Vy, nibble
-    fn process_opcode_dxyn(&mut self, opcode: u16) {
-        unimplemented!("opcode_{}", opcode);
-    }
+    // Rejects ROMs that can't be a program at all, without loading them, one too large for this
+    // VM is reported through `fits`
+    pub fn validate_rom(&self, rom: &[u8]) -> Result<RomInfo, VmError> {
+        let capacity = self.memory.len() - self.entry as usize;
 
-    fn process_opcode_e(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let op: u16 = (opcode & 0x00ff) as u16;
+        if rom.is_empty() {
+            return Err(VmError::EmptyRom);
+        }
 
-        match op {
-            0x9e => self.process_opcode_ex9e(x),
-            0xa1 => self.process_opcode_exa1(x),
-            _ => panic!("Invalid opcode {:#06x}", opcode),
+        if rom.len() % 2 != 0 {
+            return Err(VmError::OddRomLength(rom.len()));
         }
-    }
 
-    // SKP Vx
-    fn process_opcode_ex9e(&mut self, _x: u8) {
-        unimplemented!("opcode_ex9e");
+        Ok(RomInfo {
+            size: rom.len(),
+            first_opcode: (rom[0] as u16) << 8 | rom[1] as u16,
+            fits: rom.len() <= capacity,
+            free: capacity.saturating_sub(rom.len()),
+        })
     }
 
-    // SKNP Vx
-    fn process_opcode_exa1(&mut self, _x: u8) {
-        unimplemented!("opcode_exa1");
+    pub fn load_rom_bytes(&mut self, bytes: &[u8]) -> Result<(), VmError> {
+        self.load_rom_bytes_at(bytes, self.entry)
     }
 
-    fn process_opcode_f(&mut self, opcode: u16) {
-        let x: u8 = ((opcode >> 8) & 0x000f) as u8;
-        let op: u16 = (opcode & 0x00ff) as u16;
+    // Loads somewhere else than the entry, pc still starts at the entry. The VM is left
+    // untouched when the ROM does not fit
+    pub fn load_rom_bytes_at(&mut self, bytes: &[u8], address: u16) -> Result<(), VmError> {
+        let address = address as usize;
+
+        if address >= self.memory.len() {
+            return Err(VmError::OutOfBounds {
+                address,
+                size: bytes.len(),
+            });
+        }
+
+        let capacity = self.memory.len() - address;
 
-        match op {
-            0x07 => self.process_opcode_fx07(x),
-            0x0a => self.process_opcode_fx0a(x),
-            0x15 => self.process_opcode_fx15(x),
-            0x18 => self.process_opcode_fx18(x),
-            0x1e => self.process_opcode_fx1e(x),
-            0x29 => self.process_opcode_fx29(x),
-            0x33 => self.process_opcode_fx33(x),
-            0x55 => self.process_opcode_fx55(x),
-            0x65 => self.process_opcode_fx65(x),
-            _ => panic!("Invalid opcode {:#06x}", opcode),
+        if bytes.len() > capacity {
+            return Err(VmError::RomTooLarge {
+                size: bytes.len(),
+                capacity,
+            });
         }
+
+        self.reset();
+
+        self.memory[address..address + bytes.len()].copy_from_slice(bytes);
+        self.rom_start = address;
+        self.rom_size = bytes.len();
+
+        Ok(())
     }
 
-    // LD Vx, DT
-    fn process_opcode_fx07(&mut self, x: u8) {
-        self.regs.pc += 2;
-        self.regs.v[x as usize] = self.regs.delay_timer;
+    // Writes opcodes big endian one after another from `address`, nothing is written unless all
+    // of them fit
+    pub fn write_program(&mut self, address: usize, opcodes: &[u16]) -> Result<(), VmError> {
+        let bytes: Vec<u8> = opcodes
+            .iter()
+            .flat_map(|opcode| opcode.to_be_bytes())
+            .collect();
+
+        self.load_at(address, &bytes)
     }
 
-    // LD Vx, K
-    fn process_opcode_fx0a(&mut self, _x: u8) {
-        unimplemented!("opcode_fx0a");
+    // Places data anywhere in memory without resetting, nothing is written unless all of it fits
+    pub fn load_at(&mut self, address: usize, bytes: &[u8]) -> Result<(), VmError> {
+        let end = match address.checked_add(bytes.len()) {
+            Some(end) if end <= self.memory.len() => end,
+            _ => {
+                return Err(VmError::OutOfBounds {
+                    address,
+                    size: bytes.len(),
+                })
+            }
+        };
+
+        self.memory[address..end].copy_from_slice(bytes);
+        self.invalidate_decode_cache();
+
+        Ok(())
     }
 
-    // LD DT, Vx
-    fn process_opcode_fx15(&mut self, x: u8) {
-        self.regs.pc += 2;
-        self.regs.delay_timer = self.regs.v[x as usize];
+    // 64-bit FNV-1a over the loaded ROM as it currently is in memory, stable across runs and
+    // platforms so it can be stored next to save states and recordings
+    pub fn rom_hash(&self) -> u64 {
+        let start = self.rom_start;
+
+        self.memory[start..start + self.rom_size]
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
     }
 
-    // LD ST, Vx
-    fn process_opcode_fx18(&mut self, x: u8) {
-        self.regs.pc += 2;
-        self.regs.sound_timer = self.regs.v[x as usize];
+    // 16 bytes per line, offset, hex bytes and printable ASCII with '.' for everything else,
+    // wrapping around the end of memory like the interpreter does
+    pub fn hexdump(&self, start: usize, len: usize) -> String {
+        let mut dump = String::new();
+
+        for line in (0..len).step_by(16) {
+            let count = (len - line).min(16);
+            let bytes: Vec<u8> = (0..count)
+                .map(|offset| self.memory[(start + line + offset) % self.memory.len()])
+                .collect();
+
+            let _ = write!(dump, "{:04x}: ", (start + line) % self.memory.len());
+
+            for column in 0..16 {
+                match bytes.get(column) {
+                    Some(byte) => {
+                        let _ = write!(dump, "{:02x} ", byte);
+                    }
+                    None => dump.push_str("   "),
+                }
+            }
+
+            dump.push_str(" |");
+            for &byte in &bytes {
+                dump.push(if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                });
+            }
+            dump.push_str("|\n");
+        }
+
+        dump
     }
 
-    // ADD I, Vx
-    fn process_opcode_fx1e(&mut self, x: u8) {
-        self.regs.pc += 2;
-        self.regs.i += self.regs.v[x as usize] as u16;
+    pub fn snapshot(&self) -> VmSnapshot {
+        VmSnapshot {
+            version: SNAPSHOT_VERSION,
+            memory: self.memory.clone(),
+            stack: self.stack.clone(),
+            v: self.regs.v,
+            i: self.regs.i,
+            pc: self.regs.pc,
+            sp: self.regs.sp,
+            delay_timer: self.regs.delay_timer,
+            sound_timer: self.regs.sound_timer,
+            resolution: self.display.resolution(),
+            selected_planes: self.display.selected_planes(),
+            pixels: self.display.raw_pixels().to_vec(),
+            rpl: self.rpl,
+            halted: self.halted,
+            cycles: self.cycles,
+            entry: self.entry,
+            rom_start: self.rom_start,
+            rom_size: self.rom_size,
+            audio_buffer: self.audio_buffer,
+            pitch: self.pitch,
+            rng_seed: self.random.get_seed(),
+            rng_stream: self.random.get_stream(),
+            rng_word_pos: self.random.get_word_pos(),
+        }
     }
 
-    // LD F, Vx
-    fn process_opcode_fx29(&mut self, _x: u8) {
-        unimplemented!("opcode_fx29");
+    // The full machine state as a VmSnapshot binary blob, for attaching to bug reports
+    pub fn dump_core(&self) -> Vec<u8> {
+        self.snapshot().to_bytes()
     }
 
-    // LD B, Vx
-    fn process_opcode_fx33(&mut self, _x: u8) {
-        unimplemented!("opcode_fx33");
+    pub fn load_core(&mut self, bytes: &[u8]) -> Result<(), VmError> {
+        self.restore(&VmSnapshot::from_bytes(bytes)?)
     }
 
-    // LD [I], Vx
-    fn process_opcode_fx55(&mut self, x: u8) {
-        self.regs.pc += 2;
+    // Leaves the VM untouched when the snapshot is rejected, a pending fault, key wait or
+    // breakpoint resume is not part of a snapshot and gets cleared
+    pub fn restore(&mut self, snapshot: &VmSnapshot) -> Result<(), VmError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(VmError::VersionMismatch {
+                expected: SNAPSHOT_VERSION,
+                found: snapshot.version,
+            });
+        }
 
-        let address = self.regs.i as usize;
+        let (width, height) = match snapshot.resolution {
+            Resolution::Lores => (LORES_WIDTH, LORES_HEIGHT),
+            Resolution::Hires => (HIRES_WIDTH, HIRES_HEIGHT),
+        };
 
-        for i in 0..=x {
-            self.memory[address + i as usize] = self.regs.v[i as usize];
+        if snapshot.memory.len() <= INITIAL_PC as usize
+            || snapshot.entry as usize >= snapshot.memory.len()
+            || snapshot
+                .rom_start
+                .checked_add(snapshot.rom_size)
+                .map_or(true, |end| end > snapshot.memory.len())
+            || snapshot.pixels.len() != width * height
+            || snapshot.stack.is_empty()
+            || snapshot.stack.len() > u16::MAX as usize
+        {
+            return Err(VmError::InvalidSnapshot);
         }
-    }
-
-    // LD Vx, [I]
-    fn process_opcode_fx65(&mut self, x: u8) {
-        self.regs.pc += 2;
 
-        let address = self.regs.i as usize;
+        self.memory = snapshot.memory.clone();
+        self.stack = snapshot.stack.clone();
+        self.regs.v = snapshot.v;
+        self.regs.i = snapshot.i;
+        self.regs.pc = snapshot.pc;
+        self.regs.sp = snapshot.sp;
+        self.regs.delay_timer = snapshot.delay_timer;
+        self.regs.sound_timer = snapshot.sound_timer;
+        self.display.restore_raw(
+            snapshot.resolution,
+            snapshot.selected_planes,
+            &snapshot.pixels,
+        );
+        self.rpl = snapshot.rpl;
+        self.halted = snapshot.halted;
+        self.fault = None;
+        self.self_jump = None;
+        self.waiting_for_key = None;
+        self.key_wait_ticks = 0;
+        self.resume_breakpoint = None;
+        self.frame_drawn = false;
+        self.cycles = snapshot.cycles;
+        self.entry = snapshot.entry;
+        self.rom_start = snapshot.rom_start;
+        self.rom_size = snapshot.rom_size;
+        self.audio_buffer = snapshot.audio_buffer;
+        self.pitch = snapshot.pitch;
+        self.random = rand_chacha::ChaCha8Rng::from_seed(snapshot.rng_seed);
+        self.random.set_stream(snapshot.rng_stream);
+        self.random.set_word_pos(snapshot.rng_word_pos);
+
+        if self.decode_cache.is_some() {
+            self.set_decode_cache_enabled(true);
+        }
 
-        for i in 0..=x {
-            self.regs.v[i as usize] = self.memory[address + i as usize];
+        // Traced again, the old map neither matches the new memory nor has its size
+        if self.code_map.is_some() {
+            self.enable_data_guard();
         }
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Read-only views for inspectors
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
 
-    #[test]
-    fn initialize() {
-        let vm = VM::new();
-        assert_eq!(vm.memory, [0; MEMORY_SIZE]);
-        assert_eq!(vm.stack, [0; STACK_SIZE]);
-        assert_eq!(vm.regs.v, [0; V_REG_SIZE]);
-        assert_eq!(vm.regs.i, 0);
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
-        assert_eq!(vm.regs.delay_timer, 0);
-        assert_eq!(vm.regs.sound_timer, 0);
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
     }
 
-    #[test]
-    fn reset() {
-        let mut vm = VM::new();
+    // Instructions executed since the last reset
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
 
-        vm.memory.fill(1);
-        vm.stack.fill(1);
-        vm.regs.v.fill(1);
-        vm.regs.i = 1;
-        vm.regs.pc = 1;
-        vm.regs.sp = 1;
-        vm.regs.delay_timer = 1;
-        vm.regs.sound_timer = 1;
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
 
-        vm.reset();
+    // Register a pending LD Vx, K stores the key in, None once a key satisfied it
+    pub fn is_waiting_for_key(&self) -> Option<u8> {
+        self.waiting_for_key
+    }
 
-        assert_eq!(vm.memory, [0; MEMORY_SIZE]);
-        assert_eq!(vm.stack, [0; STACK_SIZE]);
-        assert_eq!(vm.regs.v, [0; V_REG_SIZE]);
-        assert_eq!(vm.regs.i, 0);
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
-        assert_eq!(vm.regs.delay_timer, 0);
-        assert_eq!(vm.regs.sound_timer, 0);
+    // SelfJump once the last step was a JP to its own address, nothing can change after that
+    // except the timers
+    pub fn status(&self) -> Status {
+        if let Some(error) = self.fault {
+            Status::Fault(error)
+        } else if self.halted {
+            Status::Halted
+        } else if self.self_jump == Some(self.regs.pc) {
+            Status::SelfJump
+        } else {
+            Status::Running
+        }
     }
 
-    #[test]
-    fn memory_read_u16() {
-        let mut vm = VM::new();
+    // The error that stopped the VM, pc is left at the faulting instruction and step does
+    // nothing until the next reset
+    pub fn fault(&self) -> Option<VmError> {
+        self.fault
+    }
 
-        let address: usize = 10;
+    fn raise(&mut self, error: VmError) {
+        self.fault = Some(error);
+    }
 
-        vm.memory[address] = 0xaa;
-        vm.memory[address + 1] = 0xbb;
+    // sp counts down from the stack size (empty) to 0 (full)
+    fn push(&mut self, address: u16) -> Result<(), VmError> {
+        let sp = self.regs.sp as usize;
 
-        let read = vm.read_u16(address);
+        if sp == 0 || sp > self.stack.len() {
+            return Err(VmError::StackOverflow);
+        }
 
-        assert_eq!(read, 0xaabb);
+        self.regs.sp -= 1;
+        self.stack[sp - 1] = address;
+        self.emit(VmEvent::StackPush(address));
+
+        Ok(())
     }
 
-    #[test]
-    fn memory_read_u8() {
+    fn pop(&mut self) -> Result<u16, VmError> {
+        let sp = self.regs.sp as usize;
+
+        if sp >= self.stack.len() {
+            return Err(VmError::StackUnderflow);
+        }
+
+        let address = self.stack[sp];
+        self.stack[sp] = 0;
+        self.regs.sp += 1;
+        self.emit(VmEvent::StackPop(address));
+
+        Ok(address)
+    }
+
+    // Hosts should play a tone for as long as this holds
+    pub fn is_buzzer_active(&self) -> bool {
+        self.regs.sound_timer > 0
+    }
+
+    // Playback rate in Hz of the 1-bit audio pattern, 4000 Hz at the default pitch
+    pub fn audio_frequency(&self) -> f64 {
+        4000.0 * libm::exp2((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    // The opcode at pc without executing it, read_u16 wraps pc past the end of memory
+    pub fn peek_opcode(&self) -> u16 {
+        self.fetch()
+    }
+
+    pub fn peek_instruction(&self) -> Result<Instruction, VmError> {
+        self.decode(self.fetch())
+    }
+
+    // step is fetch, decode and execute, along with breakpoints and the decode cache. fetch
+    // and decode leave the VM untouched, execute runs the instruction wherever pc is
+    pub fn fetch(&self) -> u16 {
+        self.read_u16(self.regs.pc as usize)
+    }
+
+    pub fn decode(&self, opcode: u16) -> Result<Instruction, VmError> {
+        Instruction::decode(opcode)
+    }
+
+    // Like instruction::is_valid_opcode, but also rejects 0nnn while strict_sys makes it fault
+    pub fn is_valid_opcode(&self, opcode: u16) -> bool {
+        match self.decode(opcode) {
+            Ok(Instruction::Sys(_)) => !self.strict_sys,
+            Ok(_) => true,
+            Err(_) => false,
+        }
+    }
+
+    pub fn execute(&mut self, instruction: Instruction) {
+        self.execute_opcode(instruction.encode(), instruction);
+    }
+
+    // Breakpoints stop step before the instruction at their address runs, the following step
+    // executes it
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    // Listing of the words from start up to end, undecodable ones as DW data
+    pub fn disassemble_rom(&self, start: u16, end: u16) -> Vec<disassembler::Line> {
+        disassembler::disassemble(&self.memory, start, end)
+    }
+
+    // Traces the code reachable from the entry in memory as it is now, and faults with
+    // ExecutingData once pc lands anywhere else. Code only reached through JP V0, addr or
+    // written at runtime is not traced and trips it too
+    pub fn enable_data_guard(&mut self) {
+        self.code_map = Some(disassembler::trace(&self.memory, self.entry));
+    }
+
+    pub fn disable_data_guard(&mut self) {
+        self.code_map = None;
+    }
+
+    // Listing of the loaded ROM that only decodes what is reachable from entry
+    pub fn disassemble_traced(&self, entry: u16) -> Vec<disassembler::Line> {
+        let start = (self.rom_start as u16).min(entry);
+        let end = (self.rom_start + self.rom_size).min(u16::MAX as usize) as u16;
+
+        disassembler::disassemble_traced(&self.memory, entry, start, end)
+    }
+
+    pub fn step(&mut self) -> StepResult {
+        let start_pc = self.regs.pc;
+
+        let (opcode, instruction) = match self.next_instruction() {
+            Ok(next) => next,
+            Err(status) => {
+                return StepResult {
+                    instruction: None,
+                    pc: start_pc,
+                    status,
+                    mnemonic: None,
+                }
+            }
+        };
+
+        // Formatted before executing, LD I, long needs the operand after the old pc, read without
+        // notifying the memory hook
+        let mnemonic = if self.trace_mnemonics {
+            Some(match instruction {
+                Instruction::LdILong => {
+                    let size = self.memory.len();
+                    let address = (start_pc as usize + 2) % size;
+                    let operand = (self.bus().read(address) as u16) << 8
+                        | self.bus().read((address + 1) % size) as u16;
+
+                    format!("{} {:#06x}", instruction, operand)
+                }
+                _ => format!("{}", instruction),
+            })
+        } else {
+            None
+        };
+
+        self.execute_opcode(opcode, instruction);
+
+        let status = if let Some(error) = self.fault {
+            StepStatus::Fault(error)
+        } else if self.halted {
+            StepStatus::Halted
+        } else if self.waiting_for_key.is_some() {
+            StepStatus::WaitingForKey
+        } else {
+            StepStatus::Ok
+        };
+
+        StepResult {
+            instruction: Some(instruction),
+            pc: start_pc,
+            status,
+            mnemonic,
+        }
+    }
+
+    // Runs up to `cycles` instructions followed by a single 60 Hz timer tick, a pending LD Vx, K
+    // ends the frame early so the timers keep running while the ROM waits for a key
+    pub fn run_frame(&mut self, cycles: usize) {
+        self.poll_key_source();
+
+        for _ in 0..cycles {
+            if self.status() != Status::Running {
+                break;
+            }
+
+            match self.step().status {
+                StepStatus::BreakpointHit | StepStatus::WaitingForKey => break,
+                _ => {}
+            }
+        }
+
+        self.tick_timers();
+    }
+
+    pub fn run_frame_default(&mut self) {
+        self.run_frame(self.cycles_per_frame);
+    }
+
+    // Runs as many frames of cycles_per_frame instructions as `dt` of wall-clock time holds at
+    // TIMER_HZ, carrying the leftover time over, so the timers track real time however often
+    // and irregularly the host calls it. Returns the number of frames run
+    pub fn advance(&mut self, dt: Duration) -> usize {
+        let frames = self.timer_clock.cycles_for_elapsed(dt);
+
+        for _ in 0..frames {
+            self.run_frame_default();
+        }
+
+        frames
+    }
+
+    // Like run_frame but budgets by Instruction::vip_cycles instead of instruction count, an
+    // instruction going over the budget is paid for by the next frame
+    pub fn run_frame_timed(&mut self, budget: u32) {
+        self.poll_key_source();
+
+        let mut spent = self.cycle_debt;
+
+        while spent < budget && self.status() == Status::Running {
+            let (opcode, instruction) = match self.next_instruction() {
+                Ok(next) => next,
+                Err(_) => break,
+            };
+
+            spent = spent.saturating_add(instruction.vip_cycles());
+            self.execute_opcode(opcode, instruction);
+
+            if self.waiting_for_key.is_some() {
+                spent = budget;
+                break;
+            }
+        }
+
+        self.cycle_debt = spent.saturating_sub(budget);
+        self.tick_timers();
+    }
+
+    // Same as calling step `cycles` times, without building a StepResult per instruction,
+    // stops early when the VM halts, faults or reaches a breakpoint and returns the number of
+    // executed instructions
+    pub fn run_n(&mut self, cycles: usize) -> usize {
+        // Breakpoints, strict_alignment and the data guard are checked before every instruction,
+        // without them only a handler can stop the run, so the fast path runs straight from the
+        // decode cache and checks for a fault or halt after executing
+        if self.decode_cache.is_none()
+            || !self.breakpoints.is_empty()
+            || self.strict_alignment
+            || self.code_map.is_some()
+        {
+            return self.run_n_checked(cycles);
+        }
+
+        if self.fault.is_some() || self.halted {
+            return 0;
+        }
+
+        self.resume_breakpoint = None;
+
+        for executed in 0..cycles {
+            let pc = self.regs.pc as usize;
+            let cached = self
+                .decode_cache
+                .as_ref()
+                .and_then(|cache| cache.get(pc).copied().flatten());
+
+            let (opcode, instruction) = match cached {
+                Some(entry) => entry,
+                None => match self.next_instruction() {
+                    Ok(next) => next,
+                    Err(_) => return executed,
+                },
+            };
+
+            self.execute_opcode(opcode, instruction);
+
+            if self.fault.is_some() || self.halted {
+                return executed + 1;
+            }
+        }
+
+        cycles
+    }
+
+    fn run_n_checked(&mut self, cycles: usize) -> usize {
+        for executed in 0..cycles {
+            match self.next_instruction() {
+                Ok((opcode, instruction)) => self.execute_opcode(opcode, instruction),
+                Err(_) => return executed,
+            }
+        }
+
+        cycles
+    }
+
+    // run_n without the observers, for skipping intros, nothing is trace logged and the event
+    // and memory hooks are detached, they are put back afterwards and get nothing for anything
+    // that happened meanwhile
+    pub fn fast_forward(&mut self, cycles: usize) -> usize {
+        let event_hook = self.event_hook.0.take();
+        let memory_hook = self.memory_hook.0.borrow_mut().take();
+        self.quiet = true;
+
+        let executed = self.run_n(cycles);
+
+        self.quiet = false;
+        self.event_hook.0 = event_hook;
+        *self.memory_hook.0.borrow_mut() = memory_hook;
+
+        executed
+    }
+
+    // Everything step does before executing, Err carries the status when nothing can run
+    fn next_instruction(&mut self) -> Result<(u16, Instruction), StepStatus> {
+        let start_pc = self.regs.pc;
+
+        if let Some(error) = self.fault {
+            return Err(StepStatus::Fault(error));
+        }
+
+        if self.halted {
+            return Err(StepStatus::Halted);
+        }
+
+        if self.resume_breakpoint.take() != Some(start_pc)
+            && !self.breakpoints.is_empty()
+            && self.has_breakpoint(start_pc)
+        {
+            self.resume_breakpoint = Some(start_pc);
+
+            return Err(StepStatus::BreakpointHit);
+        }
+
+        if self.strict_alignment && start_pc % 2 != 0 {
+            let error = VmError::MisalignedPc(start_pc);
+            self.raise(error);
+
+            return Err(StepStatus::Fault(error));
+        }
+
+        let pc = start_pc as usize % self.memory.len();
+
+        if let Some(code) = &self.code_map {
+            if !code[pc] {
+                let error = VmError::ExecutingData(start_pc);
+                self.raise(error);
+
+                return Err(StepStatus::Fault(error));
+            }
+        }
+
+        if let Some(entry) = self.decode_cache.as_ref().and_then(|cache| cache[pc]) {
+            return Ok(entry);
+        }
+
+        let opcode = self.fetch();
+        let instruction = match self.decode(opcode) {
+            Ok(instruction) => instruction,
+            Err(error) => {
+                self.raise(error);
+
+                return Err(StepStatus::Fault(error));
+            }
+        };
+
+        if let Some(cache) = &mut self.decode_cache {
+            cache[pc] = Some((opcode, instruction));
+        }
+
+        Ok((opcode, instruction))
+    }
+
+    // Steps until the ROM exits, parks itself on a jump to its own address or `max_cycles`
+    // instructions have run, without ticking the timers, every step stalled on LD Vx, K counts
+    // against `max_cycles` so a ROM waiting for a key cannot hang it
+    pub fn run_until_halt(&mut self, max_cycles: usize) -> RunResult {
+        for _ in 0..max_cycles {
+            if self.step().status == StepStatus::BreakpointHit {
+                return RunResult::BreakpointHit;
+            }
+
+            match self.status() {
+                Status::Halted => return RunResult::Halted,
+                Status::Fault(error) => return RunResult::Fault(error),
+                Status::SelfJump => return RunResult::SelfJump,
+                Status::Running => {}
+            }
+        }
+
+        match self.status() {
+            Status::Halted => RunResult::Halted,
+            Status::Fault(error) => RunResult::Fault(error),
+            _ => RunResult::CycleLimitReached,
+        }
+    }
+
+    // Like run_until_halt, but also stops right after the next CLS or DRW has executed, a DRW
+    // stalled by the display_wait quirk does not count
+    pub fn run_until_draw(&mut self, max_cycles: usize) -> RunResult {
+        for _ in 0..max_cycles {
+            let result = self.step();
+
+            if result.status == StepStatus::BreakpointHit {
+                return RunResult::BreakpointHit;
+            }
+
+            if let Some(Instruction::Cls | Instruction::Drw(..)) = result.instruction {
+                if self.regs.pc != result.pc {
+                    return RunResult::Drawn;
+                }
+            }
+
+            match self.status() {
+                Status::Halted => return RunResult::Halted,
+                Status::Fault(error) => return RunResult::Fault(error),
+                Status::SelfJump => return RunResult::SelfJump,
+                Status::Running => {}
+            }
+        }
+
+        match self.status() {
+            Status::Halted => RunResult::Halted,
+            Status::Fault(error) => RunResult::Fault(error),
+            _ => RunResult::CycleLimitReached,
+        }
+    }
+
+    // Returns VmEvent::BeepStop on the tick the sound timer reaches 0, which is also emitted
+    pub fn tick_timers(&mut self) -> Option<VmEvent> {
+        self.frame_drawn = false;
+        self.display.tick_persistence();
+
+        if self.waiting_for_key.is_some() {
+            self.key_wait_ticks = self.key_wait_ticks.saturating_add(1);
+        } else {
+            self.key_wait_ticks = 0;
+        }
+
+        self.regs.delay_timer = self.regs.delay_timer.saturating_sub(1);
+        self.set_sound_timer(self.regs.sound_timer.saturating_sub(1))
+    }
+
+    // BeepStart on 0 -> nonzero, BeepStop on nonzero -> 0
+    fn set_sound_timer(&mut self, value: u8) -> Option<VmEvent> {
+        let was_active = self.is_buzzer_active();
+        self.regs.sound_timer = value;
+
+        let event = match (was_active, self.is_buzzer_active()) {
+            (false, true) => VmEvent::BeepStart,
+            (true, false) => VmEvent::BeepStop,
+            _ => return None,
+        };
+
+        self.emit(event);
+
+        Some(event)
+    }
+
+    // An opcode that does not decode faults with InvalidOpcode like it would when stepped
+    pub fn process_opcode(&mut self, opcode: u16) {
+        match Instruction::decode(opcode) {
+            Ok(instruction) => self.execute_opcode(opcode, instruction),
+            Err(error) => self.raise(error),
+        }
+    }
+
+    fn execute_opcode(&mut self, opcode: u16, instruction: Instruction) {
+        let op = (opcode >> 12) as u8;
+
+        if !self.quiet {
+            trace!("opcode: {:#06x} op: {:#04x}", opcode, op);
+        }
+
+        if self.opcode_stats_enabled {
+            self.opcode_stats[op as usize] += 1;
+            self.opcode_coverage.insert(instruction.pattern());
+        }
+
+        self.self_jump = None;
+        self.waiting_for_key = None;
+        self.cycles += 1;
+
+        self.dispatch(instruction);
+
+        // Keeps pc inside memory, execution continues from the bottom past the top
+        self.regs.pc = (self.regs.pc as usize % self.memory.len()) as u16;
+    }
+
+    // Register access for the handlers, x is masked to a nibble like the opcode encodes it
+    fn vx(&self, x: u8) -> u8 {
+        self.regs.v[(x & 0x0f) as usize]
+    }
+
+    fn set_vx(&mut self, x: u8, value: u8) {
+        self.regs.v[(x & 0x0f) as usize] = value;
+    }
+
+    // Moves pc on to the next instruction
+    fn advance_pc(&mut self) {
+        self.regs.pc = self.regs.pc.wrapping_add(2);
+    }
+
+    fn dispatch(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Sys(nnn) => self.process_opcode_0nnn(nnn),
+            Instruction::Scd(n) => self.process_opcode_00cn(n),
+            Instruction::Scu(n) => self.process_opcode_00dn(n),
+            Instruction::Cls => self.process_opcode_00e0(),
+            Instruction::Ret => self.process_opcode_00ee(),
+            Instruction::Scr => self.process_opcode_00fb(),
+            Instruction::Scl => self.process_opcode_00fc(),
+            Instruction::Exit => self.process_opcode_00fd(),
+            Instruction::Low => self.process_opcode_00fe(),
+            Instruction::High => self.process_opcode_00ff(),
+            Instruction::Jp(nnn) => self.process_opcode_1nnn(nnn),
+            Instruction::Call(nnn) => self.process_opcode_2nnn(nnn),
+            Instruction::SeByte(x, kk) => self.process_opcode_3xkk(x, kk),
+            Instruction::SneByte(x, kk) => self.process_opcode_4xkk(x, kk),
+            Instruction::SeReg(x, y) => self.process_opcode_5xy0(x, y),
+            Instruction::LdByte(x, kk) => self.process_opcode_6xkk(x, kk),
+            Instruction::AddByte(x, kk) => self.process_opcode_7xkk(x, kk),
+            Instruction::LdReg(x, y) => self.process_opcode_8xy0(x, y),
+            Instruction::Or(x, y) => self.process_opcode_8xy1(x, y),
+            Instruction::And(x, y) => self.process_opcode_8xy2(x, y),
+            Instruction::Xor(x, y) => self.process_opcode_8xy3(x, y),
+            Instruction::AddReg(x, y) => self.process_opcode_8xy4(x, y),
+            Instruction::Sub(x, y) => self.process_opcode_8xy5(x, y),
+            Instruction::Shr(x, y) => self.process_opcode_8xy6(x, y),
+            Instruction::Subn(x, y) => self.process_opcode_8xy7(x, y),
+            Instruction::Shl(x, y) => self.process_opcode_8xye(x, y),
+            Instruction::SneReg(x, y) => self.process_opcode_9xy0(x, y),
+            Instruction::LdI(nnn) => self.process_opcode_annn(nnn),
+            Instruction::JpV0(nnn) => self.process_opcode_bnnn(nnn),
+            Instruction::Rnd(x, kk) => self.process_opcode_cxkk(x, kk),
+            Instruction::Drw(x, y, n) => self.process_opcode_dxyn(x, y, n),
+            Instruction::Skp(x) => self.process_opcode_ex9e(x),
+            Instruction::Sknp(x) => self.process_opcode_exa1(x),
+            Instruction::LdILong => self.process_opcode_f000(),
+            Instruction::Plane(x) => self.process_opcode_fx01(x),
+            Instruction::Audio => self.process_opcode_f002(),
+            Instruction::LdVxDt(x) => self.process_opcode_fx07(x),
+            Instruction::LdVxK(x) => self.process_opcode_fx0a(x),
+            Instruction::LdDtVx(x) => self.process_opcode_fx15(x),
+            Instruction::LdStVx(x) => self.process_opcode_fx18(x),
+            Instruction::AddI(x) => self.process_opcode_fx1e(x),
+            Instruction::LdF(x) => self.process_opcode_fx29(x),
+            Instruction::LdHf(x) => self.process_opcode_fx30(x),
+            Instruction::LdB(x) => self.process_opcode_fx33(x),
+            Instruction::Pitch(x) => self.process_opcode_fx3a(x),
+            Instruction::LdIVx(x) => self.process_opcode_fx55(x),
+            Instruction::LdVxI(x) => self.process_opcode_fx65(x),
+            Instruction::LdRVx(x) => self.process_opcode_fx75(x),
+            Instruction::LdVxR(x) => self.process_opcode_fx85(x),
+        }
+    }
+
+    fn scroll_amount(&self, n: usize) -> usize {
+        match self.display.resolution() {
+            Resolution::Lores if self.quirks.lores_scroll_half => n / 2,
+            _ => n,
+        }
+    }
+
+    // SCD nibble
+    fn process_opcode_00cn(&mut self, n: u8) {
+        let n = self.scroll_amount(n as usize);
+
+        self.advance_pc();
+        self.display.scroll_down(n);
+    }
+
+    // SCU nibble
+    fn process_opcode_00dn(&mut self, n: u8) {
+        let n = self.scroll_amount(n as usize);
+
+        self.advance_pc();
+        self.display.scroll_up(n);
+    }
+
+    // CLS
+    fn process_opcode_00e0(&mut self) {
+        self.advance_pc();
+        self.display.clear();
+        self.emit(VmEvent::Cls);
+    }
+
+    // RET
+    fn process_opcode_00ee(&mut self) {
+        match self.pop() {
+            Ok(address) => self.regs.pc = address,
+            Err(error) => self.raise(error),
+        }
+    }
+
+    // SCR
+    fn process_opcode_00fb(&mut self) {
+        let n = self.scroll_amount(4);
+
+        self.advance_pc();
+        self.display.scroll_right(n);
+    }
+
+    // SCL
+    fn process_opcode_00fc(&mut self) {
+        let n = self.scroll_amount(4);
+
+        self.advance_pc();
+        self.display.scroll_left(n);
+    }
+
+    // EXIT
+    fn process_opcode_00fd(&mut self) {
+        self.halted = true;
+        self.emit(VmEvent::Halt);
+    }
+
+    // LOW
+    fn process_opcode_00fe(&mut self) {
+        self.advance_pc();
+        self.display.set_resolution(Resolution::Lores);
+    }
+
+    // HIGH
+    fn process_opcode_00ff(&mut self) {
+        self.advance_pc();
+        self.display.set_resolution(Resolution::Hires);
+    }
+
+    // SYS addr
+    fn process_opcode_0nnn(&mut self, nnn: u16) {
+        if self.strict_sys {
+            self.raise(VmError::UnsupportedSys(nnn));
+            return;
+        }
+
+        self.advance_pc();
+    }
+
+    // JP addr
+    fn process_opcode_1nnn(&mut self, nnn: u16) {
+        if self.regs.pc == nnn {
+            self.self_jump = Some(nnn);
+        }
+        self.regs.pc = nnn;
+    }
+
+    // CALL addr
+    fn process_opcode_2nnn(&mut self, nnn: u16) {
+        let return_address = (self.regs.pc as usize + 2) % self.memory.len();
+
+        match self.push(return_address as u16) {
+            Ok(()) => self.regs.pc = nnn,
+            Err(error) => self.raise(error),
+        }
+    }
+
+    // SE Vx, byte
+    fn process_opcode_3xkk(&mut self, x: u8, kk: u8) {
+        self.advance_pc();
+
+        if self.vx(x) == kk {
+            self.advance_pc();
+        }
+    }
+
+    // SNE Vx, byte
+    fn process_opcode_4xkk(&mut self, x: u8, kk: u8) {
+        self.advance_pc();
+
+        if self.vx(x) != kk {
+            self.advance_pc();
+        }
+    }
+
+    // SE Vx, Vy
+    fn process_opcode_5xy0(&mut self, x: u8, y: u8) {
+        self.advance_pc();
+
+        if self.vx(x) == self.vx(y) {
+            self.advance_pc();
+        }
+    }
+
+    // LD Vx, byte
+    fn process_opcode_6xkk(&mut self, x: u8, kk: u8) {
+        self.advance_pc();
+        self.set_vx(x, kk);
+    }
+
+    // ADD Vx, byte
+    fn process_opcode_7xkk(&mut self, x: u8, kk: u8) {
+        self.advance_pc();
+        self.set_vx(x, self.vx(x).wrapping_add(kk));
+    }
+
+    // LD Vx, Vy
+    fn process_opcode_8xy0(&mut self, x: u8, y: u8) {
+        self.advance_pc();
+        self.set_vx(x, self.vx(y));
+    }
+
+    // OR Vx, Vy
+    fn process_opcode_8xy1(&mut self, x: u8, y: u8) {
+        self.advance_pc();
+        self.set_vx(x, self.vx(x) | self.vx(y));
+    }
+
+    // AND Vx, Vy
+    fn process_opcode_8xy2(&mut self, x: u8, y: u8) {
+        self.advance_pc();
+        self.set_vx(x, self.vx(x) & self.vx(y));
+    }
+
+    // XOR Vx, Vy
+    fn process_opcode_8xy3(&mut self, x: u8, y: u8) {
+        self.advance_pc();
+        self.set_vx(x, self.vx(x) ^ self.vx(y));
+    }
+
+    // ADD Vx, Vy
+    fn process_opcode_8xy4(&mut self, x: u8, y: u8) {
+        let value: u16 = self.vx(x) as u16 + self.vx(y) as u16;
+
+        self.advance_pc();
+        self.set_vx(x, (value & 0x00ff) as u8);
+        self.set_vx(0xf, if value > 255 { 1 } else { 0 });
+    }
+
+    // SUB Vx, Vy
+    fn process_opcode_8xy5(&mut self, x: u8, y: u8) {
+        let (vx, vy) = (self.vx(x), self.vx(y));
+
+        // The flag is written last so it wins over the result for SUB VF, Vy
+        self.advance_pc();
+        self.set_vx(x, vx.wrapping_sub(vy));
+        self.set_vx(0xf, if vx >= vy { 1 } else { 0 });
+    }
+
+    // SHR Vx {, Vy}
+    fn process_opcode_8xy6(&mut self, x: u8, _y: u8) {
+        let vx = self.vx(x);
+
+        self.advance_pc();
+        self.set_vx(x, vx >> 1);
+        self.set_vx(0xf, vx & 1);
+    }
+
+    // SUBN Vx, Vy
+    fn process_opcode_8xy7(&mut self, x: u8, y: u8) {
+        let (vx, vy) = (self.vx(x), self.vx(y));
+
+        self.advance_pc();
+        self.set_vx(x, vy.wrapping_sub(vx));
+        self.set_vx(0xf, if vy >= vx { 1 } else { 0 });
+    }
+
+    // SHL Vx {, Vy}
+    fn process_opcode_8xye(&mut self, x: u8, _y: u8) {
+        let vx = self.vx(x);
+
+        self.advance_pc();
+        self.set_vx(x, vx << 1);
+        self.set_vx(0xf, vx >> 7);
+    }
+
+    // SNE Vx, Vy
+    fn process_opcode_9xy0(&mut self, x: u8, y: u8) {
+        self.advance_pc();
+
+        if self.vx(x) != self.vx(y) {
+            self.advance_pc();
+        }
+    }
+
+    // LD I, addr
+    fn process_opcode_annn(&mut self, nnn: u16) {
+        self.advance_pc();
+        self.regs.i = nnn;
+    }
+
+    // JP V0, addr
+    fn process_opcode_bnnn(&mut self, nnn: u16) {
+        self.regs.pc = self.vx(0) as u16 + nnn;
+    }
+
+    // RND Vx, byte
+    fn process_opcode_cxkk(&mut self, x: u8, kk: u8) {
+        self.advance_pc();
+        let value = self.random.gen::<u8>() & kk;
+
+        self.set_vx(x, value);
+    }
+
+    // DRW Vx, Vy, nibble
+    fn process_opcode_dxyn(&mut self, x: u8, y: u8, n: u8) {
+        // Stall on the same DRW until the next vertical blank
+        if self.quirks.display_wait {
+            if self.frame_drawn {
+                return;
+            }
+
+            self.frame_drawn = true;
+        }
+
+        self.advance_pc();
+
+        let start_x = self.vx(x) as usize;
+        let start_y = self.vx(y) as usize;
+        let mut address = self.regs.i as usize;
+        let mut collision = false;
+
+        // SUPER-CHIP draws a 16x16 sprite, two bytes per row, when n == 0
+        let (size, sprite_width) = if n == 0 { (32, 16) } else { (n as usize, 8) };
+        let mut sprite = [0; 32];
+
+        // XO-CHIP reads consecutive sprite data for every selected plane and sets VF when any of
+        // them had a pixel erased, reads past the end of memory wrap around to address 0
+        for plane in 0..MAX_PLANES {
+            if self.display.selected_planes() & (1 << plane) == 0 {
+                continue;
+            }
+
+            for (offset, byte) in sprite[..size].iter_mut().enumerate() {
+                *byte = self.read_u8(address + offset);
+            }
+
+            if self.draw_plane_sprite(plane, start_x, start_y, &sprite[..size], sprite_width) {
+                collision = true;
+            }
+
+            address += size;
+        }
+
+        self.set_vx(0xf, if collision { 1 } else { 0 });
+        self.emit(VmEvent::Drw {
+            x: start_x as u8,
+            y: start_y as u8,
+            collision,
+        });
+    }
+
+    // XORs an 8 pixel wide sprite onto every selected plane like DRW does, without touching VF,
+    // returns whether any lit pixel got erased
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        let mut collision = false;
+
+        for plane in 0..MAX_PLANES {
+            if self.display.selected_planes() & (1 << plane) != 0
+                && self.draw_plane_sprite(plane, x, y, sprite, 8)
+            {
+                collision = true;
+            }
+        }
+
+        collision
+    }
+
+    fn draw_plane_sprite(
+        &mut self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        sprite: &[u8],
+        sprite_width: usize,
+    ) -> bool {
+        let width = self.display.width();
+        let height = self.display.height();
+        let start_x = x % width;
+        let start_y = y % height;
+        let row_bytes = sprite_width / 8;
+        let mut collision = false;
+
+        for (row, bytes) in sprite.chunks(row_bytes).enumerate() {
+            let mut py = start_y + row;
+            if py >= height {
+                if self.quirks.sprite_clip_bottom || !self.quirks.sprite_wrap {
+                    break;
+                }
+                py %= height;
+            }
+
+            for bit in 0..bytes.len() * 8 {
+                let mut px = start_x + bit;
+                if px >= width {
+                    if !self.quirks.sprite_wrap {
+                        break;
+                    }
+                    px %= width;
+                }
+
+                if bytes[bit / 8] & (0x80 >> (bit % 8)) != 0
+                    && self.display.xor_plane_pixel(plane, px, py)
+                {
+                    collision = true;
+                }
+            }
+        }
+
+        collision
+    }
+
+    // SKP Vx
+    fn process_opcode_ex9e(&mut self, x: u8) {
+        self.advance_pc();
+
+        if self.keypad.is_pressed(self.vx(x)) {
+            self.advance_pc();
+        }
+    }
+
+    // SKNP Vx
+    fn process_opcode_exa1(&mut self, x: u8) {
+        self.advance_pc();
+
+        if !self.keypad.is_pressed(self.vx(x)) {
+            self.advance_pc();
+        }
+    }
+
+    // LD I, long addr
+    fn process_opcode_f000(&mut self) {
+        let address = self.read_u16(self.regs.pc as usize + 2);
+
+        self.regs.pc = self.regs.pc.wrapping_add(4);
+        self.regs.i = address;
+    }
+
+    // PLANE n
+    fn process_opcode_fx01(&mut self, x: u8) {
+        self.advance_pc();
+        self.display.select_planes(x);
+    }
+
+    // AUDIO
+    fn process_opcode_f002(&mut self) {
+        self.advance_pc();
+
+        let address = self.regs.i as usize;
+
+        for i in 0..AUDIO_BUFFER_SIZE {
+            self.audio_buffer[i] = self.read_u8(address + i);
+        }
+    }
+
+    // LD Vx, DT
+    fn process_opcode_fx07(&mut self, x: u8) {
+        self.advance_pc();
+        self.set_vx(x, self.regs.delay_timer);
+    }
+
+    // LD Vx, K
+    fn process_opcode_fx0a(&mut self, x: u8) {
+        // Stays on this instruction until a key is down or key_wait_timeout ticks went by
+        let timed_out = self
+            .key_wait_timeout
+            .is_some_and(|timeout| self.key_wait_ticks >= timeout);
+
+        let key = match self.keypad.first_pressed() {
+            Some(key) => key,
+            None if timed_out => match self.key_wait_default {
+                Some(key) => key,
+                None => {
+                    self.key_wait_ticks = 0;
+                    self.raise(VmError::KeyWaitTimeout);
+                    return;
+                }
+            },
+            None => {
+                self.waiting_for_key = Some(x);
+                return;
+            }
+        };
+
+        // The next LD Vx, K waits the full timeout again
+        self.key_wait_ticks = 0;
+        self.advance_pc();
+        self.set_vx(x, key);
+    }
+
+    // LD DT, Vx
+    fn process_opcode_fx15(&mut self, x: u8) {
+        self.advance_pc();
+        self.regs.delay_timer = self.vx(x);
+    }
+
+    // LD ST, Vx
+    fn process_opcode_fx18(&mut self, x: u8) {
+        self.advance_pc();
+        self.set_sound_timer(self.vx(x));
+    }
+
+    // ADD I, Vx
+    fn process_opcode_fx1e(&mut self, x: u8) {
+        self.advance_pc();
+        self.regs.i = self.regs.i.wrapping_add(self.vx(x) as u16);
+    }
+
+    // LD F, Vx
+    fn process_opcode_fx29(&mut self, x: u8) {
+        let digit = (self.vx(x) & 0x0f) as usize;
+
+        self.advance_pc();
+        self.regs.i = (FONT_START + digit * FONT_GLYPH_SIZE) as u16;
+    }
+
+    // LD HF, Vx
+    fn process_opcode_fx30(&mut self, x: u8) {
+        let digit = (self.vx(x) & 0x0f) as usize;
+
+        self.advance_pc();
+        self.regs.i = (BIG_FONT_START + digit * BIG_FONT_GLYPH_SIZE) as u16;
+    }
+
+    // LD B, Vx
+    fn process_opcode_fx33(&mut self, x: u8) {
+        let address = self.regs.i as usize;
+        let value = self.vx(x);
+
+        if let Err(error) = self.check_writable(address, 3) {
+            self.raise(error);
+            return;
+        }
+
+        self.advance_pc();
+
+        self.write_u8(address, value / 100);
+        self.write_u8(address + 1, value / 10 % 10);
+        self.write_u8(address + 2, value % 10);
+    }
+
+    // PITCH Vx
+    fn process_opcode_fx3a(&mut self, x: u8) {
+        self.advance_pc();
+        self.pitch = self.vx(x);
+    }
+
+    // LD [I], Vx
+    fn process_opcode_fx55(&mut self, x: u8) {
+        let address = self.regs.i as usize;
+
+        if let Err(error) = self.check_writable(address, x as usize + 1) {
+            self.raise(error);
+            return;
+        }
+
+        self.advance_pc();
+
+        for i in 0..=x {
+            self.write_u8(address + i as usize, self.vx(i));
+        }
+    }
+
+    // LD Vx, [I]
+    fn process_opcode_fx65(&mut self, x: u8) {
+        self.advance_pc();
+
+        let address = self.regs.i as usize;
+
+        for i in 0..=x {
+            self.set_vx(i, self.read_u8(address + i as usize));
+        }
+    }
+
+    // LD R, Vx
+    fn process_opcode_fx75(&mut self, x: u8) {
+        let count = (x as usize).min(RPL_SIZE - 1) + 1;
+
+        self.advance_pc();
+        self.rpl[..count].copy_from_slice(&self.regs.v[..count]);
+    }
+
+    // LD Vx, R
+    fn process_opcode_fx85(&mut self, x: u8) {
+        let count = (x as usize).min(RPL_SIZE - 1) + 1;
+
+        self.advance_pc();
+        self.regs.v[..count].copy_from_slice(&self.rpl[..count]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_memory_cleared(vm: &VM) {
+        let font_end = BIG_FONT_START + BIG_FONT.len();
+
+        assert_eq!(vm.memory[FONT_START..FONT_START + FONT.len()], FONT);
+        assert_eq!(vm.memory[BIG_FONT_START..font_end], BIG_FONT);
+        assert!(vm.memory[..FONT_START].iter().all(|&byte| byte == 0));
+        assert!(vm.memory[font_end..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn vm_is_send() {
+        fn assert_send<T: Send>() {}
+
+        assert_send::<VM>();
+    }
+
+    #[test]
+    fn initialize() {
+        let vm = VM::new();
+        assert_memory_cleared(&vm);
+        assert_eq!(vm.stack, [0; STACK_SIZE]);
+        assert_eq!(vm.regs.v, [0; V_REG_SIZE]);
+        assert_eq!(vm.regs.i, 0);
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+        assert_eq!(vm.regs.delay_timer, 0);
+        assert_eq!(vm.regs.sound_timer, 0);
+    }
+
+    #[test]
+    fn reset() {
+        let mut vm = VM::new();
+
+        vm.memory.fill(1);
+        vm.stack.fill(1);
+        vm.regs.v.fill(1);
+        vm.regs.i = 1;
+        vm.regs.pc = 1;
+        vm.regs.sp = 1;
+        vm.regs.delay_timer = 1;
+        vm.regs.sound_timer = 1;
+
+        vm.reset();
+
+        assert_memory_cleared(&vm);
+        assert_eq!(vm.stack, [0; STACK_SIZE]);
+        assert_eq!(vm.regs.v, [0; V_REG_SIZE]);
+        assert_eq!(vm.regs.i, 0);
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+        assert_eq!(vm.regs.delay_timer, 0);
+        assert_eq!(vm.regs.sound_timer, 0);
+    }
+
+    fn load_dispatch_program(vm: &mut VM) {
+        let program = [
+            0x6000, // 0x200: LD V0, 0x00
+            0x6101, // 0x202: LD V1, 0x01
+            0xa300, // 0x204: LD I, 0x300
+            0x8014, // 0x206: ADD V0, V1
+            0x8203, // 0x208: XOR V2, V0
+            0x8301, // 0x20a: OR V3, V0
+            0x8306, // 0x20c: SHR V3
+            0x8522, // 0x20e: AND V5, V2
+            0x8621, // 0x210: OR V6, V2
+            0xc70f, // 0x212: RND V7, 0x0f
+            0xf729, // 0x214: LD F, V7
+            0xd015, // 0x216: DRW V0, V1, 5
+            0xa300, // 0x218: LD I, 0x300
+            0xf255, // 0x21a: LD [I], V2
+            0x3000, // 0x21c: SE V0, 0x00
+            0x1206, // 0x21e: JP 0x206
+            0x8414, // 0x220: ADD V4, V1
+            0xf418, // 0x222: LD ST, V4
+            0x5450, // 0x224: SE V4, V5
+            0x1206, // 0x226: JP 0x206
+        ];
+
+        vm.write_program(INITIAL_PC as usize, &program).unwrap();
+    }
+
+    #[test]
+    fn decode_cache_parity() {
+        let mut vm = VM::new();
+        let mut reference = VM::new();
+
+        vm.set_decode_cache_enabled(true);
+        load_dispatch_program(&mut vm);
+        load_dispatch_program(&mut reference);
+
+        for _ in 0..100_000 {
+            vm.step();
+            reference.step();
+        }
+
+        assert_eq!(vm.regs.v, reference.regs.v);
+        assert_eq!(vm.regs.i, reference.regs.i);
+        assert_eq!(vm.regs.pc, reference.regs.pc);
+        assert_eq!(vm.regs.sp, reference.regs.sp);
+        assert_eq!(vm.regs.sound_timer, reference.regs.sound_timer);
+        assert_eq!(vm.memory, reference.memory);
+        assert_eq!(
+            vm.display.to_rgba([1; 4], [0; 4]),
+            reference.display.to_rgba([1; 4], [0; 4])
+        );
+        assert_ne!(vm.regs.v[4], 0);
+    }
+
+    #[test]
+    fn decode_cache_invalidation() {
+        let mut vm = VM::new();
+
+        vm.set_decode_cache_enabled(true);
+
+        assert!(vm.is_decode_cache_enabled());
+
+        vm.write_u16(0x200, 0x6001); // LD V0, 0x01
+        vm.write_u16(0x202, 0x6101); // LD V1, 0x01
+        vm.write_u16(0x204, 0x1200); // JP 0x200
+
+        for _ in 0..3 {
+            vm.step();
+        }
+
+        assert_eq!(vm.regs.v[0], 1);
+        assert_eq!(vm.regs.v[1], 1);
+
+        vm.write_u8(0x203, 0x02); // LD V1, 0x02
+        vm.write_u8(0x200, 0x70); // ADD V0, 0x01
+
+        for _ in 0..3 {
+            vm.step();
+        }
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0], 2);
+        assert_eq!(vm.regs.v[1], 2);
+
+        vm.memory[0x202] = 0x62; // LD V2, 0x02
+        vm.invalidate_decode_cache();
+
+        for _ in 0..3 {
+            vm.step();
+        }
+
+        assert_eq!(vm.regs.v[0], 3);
+        assert_eq!(vm.regs.v[2], 2);
+
+        vm.set_decode_cache_enabled(false);
+
+        assert!(!vm.is_decode_cache_enabled());
+    }
+
+    #[test]
+    fn decode_cache_self_modifying() {
+        let mut vm = VM::new();
+
+        vm.set_decode_cache_enabled(true);
+
+        let program = [
+            0x220c, // CALL 0x20c
+            0x6063, // LD V0, 0x63
+            0x6107, // LD V1, 0x07
+            0xa20c, // LD I, 0x20c
+            0xf155, // LD [I], V1
+            0x220c, // CALL 0x20c
+            0x6301, // LD V3, 0x01
+            0x00ee, // RET
+        ];
+        vm.write_program(0x200, &program).unwrap();
+
+        for _ in 0..3 {
+            vm.step();
+        }
+
+        assert_eq!(vm.regs.v[3], 1);
+
+        for _ in 0..7 {
+            vm.step();
+        }
+
+        assert_eq!(vm.read_u16(0x20c), 0x6307);
+        assert_eq!(vm.regs.v[3], 7);
+        assert_eq!(vm.regs.pc, 0x20c);
+    }
+
+    #[test]
+    fn opcode_stats() {
+        let mut vm = VM::new();
+
+        let program = [
+            0x6000, // LD V0, 0x00
+            0x7001, // ADD V0, 0x01
+            0x3005, // SE V0, 0x05
+            0x1202, // JP 0x202
+        ];
+        vm.write_program(0x200, &program).unwrap();
+
+        for _ in 0..15 {
+            vm.step();
+        }
+
+        assert_eq!(vm.opcode_stats(), &[0; OPCODE_CLASSES]);
+
+        vm.regs.pc = INITIAL_PC;
+        vm.opcode_stats_enabled = true;
+
+        for _ in 0..15 {
+            vm.step();
+        }
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 8);
+
+        let stats = vm.opcode_stats();
+        assert_eq!(stats[0x1], 4);
+        assert_eq!(stats[0x3], 5);
+        assert_eq!(stats[0x6], 1);
+        assert_eq!(stats[0x7], 5);
+        assert_eq!(stats.iter().sum::<u64>(), 15);
+
+        vm.reset_opcode_stats();
+
+        assert_eq!(vm.opcode_stats(), &[0; OPCODE_CLASSES]);
+    }
+
+    #[test]
+    fn memory_read_u16() {
+        let mut vm = VM::new();
+
+        let address: usize = 10;
+
+        vm.memory[address] = 0xaa;
+        vm.memory[address + 1] = 0xbb;
+
+        let read = vm.read_u16(address);
+
+        assert_eq!(read, 0xaabb);
+    }
+
+    #[test]
+    fn memory_size() {
+        let mut vm = VM::with_memory_size(XO_MEMORY_SIZE);
+
+        assert_eq!(vm.memory_size(), XO_MEMORY_SIZE);
+        assert_eq!(vm.memory.len(), XO_MEMORY_SIZE);
+        assert_memory_cleared(&vm);
+
+        vm.write_u16(0xfffc, 0xaabb);
+        vm.write_u8(0xfffe, 0xcc);
+
+        assert_eq!(vm.read_u16(0xfffc), 0xaabb);
+        assert_eq!(vm.read_u8(0xfffe), 0xcc);
+        assert_eq!(vm.memory[0xfffc], 0xaa);
+        assert_eq!(vm.memory[0xfffd], 0xbb);
+
+        vm.write_u16(0xffff, 0x1234);
+
+        assert_eq!(vm.memory[0xffff], 0x12);
+        assert_eq!(vm.memory[0x0000], 0x34);
+        assert_eq!(vm.read_u16(0xffff), 0x1234);
+
+        vm.reset();
+
+        assert_eq!(vm.memory_size(), XO_MEMORY_SIZE);
+        assert_memory_cleared(&vm);
+    }
+
+    #[test]
+    fn memory_size_default() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.memory_size(), MEMORY_SIZE);
+
+        vm.write_u16(MEMORY_SIZE - 1, 0xaabb);
+
+        assert_eq!(vm.memory[MEMORY_SIZE - 1], 0xaa);
+        assert_eq!(vm.memory[0], 0xbb);
+        assert_eq!(vm.read_u8(MEMORY_SIZE), 0xbb);
+    }
+
+    #[test]
+    fn opcode_f000_high_memory() {
+        let mut vm = VM::with_memory_size(XO_MEMORY_SIZE);
+
+        vm.write_u8(0xf000, 0xaa);
+        vm.write_u16(vm.regs.pc as usize, 0xf000); // LD I, long 0xf000
+        vm.write_u16(vm.regs.pc as usize + 2, 0xf000);
+        vm.write_u16(vm.regs.pc as usize + 4, 0xf065); // LD V0, [I]
+        vm.step();
+        vm.step();
+
+        assert_eq!(vm.regs.i, 0xf000);
+        assert_eq!(vm.regs.v[0], 0xaa);
+    }
+
+    #[test]
+    fn memory_read_u8() {
+        let mut vm = VM::new();
+
+        let address: usize = 10;
+
+        vm.memory[address] = 0xaa;
+
+        let read = vm.read_u8(address);
+
+        assert_eq!(read, 0xaa);
+    }
+
+    #[test]
+    fn memory_write_u16() {
+        let mut vm = VM::new();
+
+        let address: usize = 10;
+
+        vm.write_u16(address, 0xaabb);
+
+        assert_eq!(vm.memory[address], 0xaa);
+        assert_eq!(vm.memory[address + 1], 0xbb);
+    }
+
+    #[test]
+    fn memory_write_u8() {
+        let mut vm = VM::new();
+
+        let address: usize = 10;
+
+        vm.write_u8(address, 0xaa);
+
+        assert_eq!(vm.memory[address], 0xaa);
+    }
+
+    #[test]
+    fn tick_timers() {
+        let mut vm = VM::new();
+
+        vm.regs.delay_timer = 2;
+        vm.regs.sound_timer = 1;
+        vm.tick_timers();
+
+        assert_eq!(vm.regs.delay_timer, 1);
+        assert_eq!(vm.regs.sound_timer, 0);
+
+        vm.tick_timers();
+
+        assert_eq!(vm.regs.delay_timer, 0);
+        assert_eq!(vm.regs.sound_timer, 0);
+    }
+
+    #[test]
+    fn buzzer() {
+        let mut vm = VM::new();
+
+        assert!(!vm.is_buzzer_active());
+
+        vm.regs.v[0] = 3;
+        vm.write_u16(vm.regs.pc as usize, 0xf018); // LD ST, V0
+        vm.step();
+
+        for _ in 0..3 {
+            assert!(vm.is_buzzer_active());
+            vm.tick_timers();
+        }
+
+        assert!(!vm.is_buzzer_active());
+
+        vm.tick_timers();
+
+        assert!(!vm.is_buzzer_active());
+    }
+
+    #[test]
+    fn run_frame() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0x7001); // ADD V0, 0x01
+        vm.write_u16(0x202, 0x1200); // JP 0x200
+        vm.regs.delay_timer = 5;
+
+        vm.run_frame(10);
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0], 5);
+        assert_eq!(vm.regs.delay_timer, 4);
+
+        vm.write_u16(0x200, 0x00fd); // EXIT
+        vm.run_frame(10);
+
+        assert!(vm.is_halted());
+        assert_eq!(vm.regs.delay_timer, 3);
+    }
+
+    #[test]
+    fn opcode_00ee() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        let sp = vm.regs.sp - 1;
+        vm.regs.sp = sp;
+        vm.stack[vm.regs.sp as usize] = vm.regs.pc;
+        vm.regs.pc += 2;
+
+        vm.write_u16(vm.regs.pc as usize, 0x00ee); // RET
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+        assert_eq!(vm.stack[sp as usize], 0);
+    }
+
+    #[test]
+    fn opcode_00ee_underflow() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0x00ee); // RET
+        vm.step();
+
+        assert_eq!(vm.fault(), Some(VmError::StackUnderflow));
+        assert_eq!(vm.status(), Status::Fault(VmError::StackUnderflow));
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+
+        vm.regs.sp = 0xffff;
+        vm.reset();
+
+        assert_eq!(vm.status(), Status::Running);
+
+        vm.regs.sp = STACK_SIZE as u16 + 1;
+        vm.write_u16(0x200, 0x00ee); // RET
+        vm.step();
+
+        assert_eq!(vm.fault(), Some(VmError::StackUnderflow));
+    }
+
+    #[test]
+    fn opcode_00cn() {
+        let mut vm = VM::new();
+        vm.quirks.lores_scroll_half = true;
+
+        vm.write_u16(vm.regs.pc as usize, 0x00ff); // HIGH
+        vm.step();
+
+        vm.display.set_pixel(10, 0, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00c3); // SCD 3
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert!(!vm.display.pixel(10, 0));
+        assert!(vm.display.pixel(10, 3));
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fe); // LOW
+        vm.step();
+
+        vm.display.set_pixel(10, 0, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00c4); // SCD 4
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 8);
+        assert!(!vm.display.pixel(10, 0));
+        assert!(vm.display.pixel(10, 2));
+    }
+
+    #[test]
+    fn opcode_00fb() {
+        let mut vm = VM::new();
+        vm.quirks.lores_scroll_half = true;
+
+        vm.write_u16(vm.regs.pc as usize, 0x00ff); // HIGH
+        vm.step();
+
+        vm.display.set_pixel(10, 5, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fb); // SCR
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert!(!vm.display.pixel(10, 5));
+        assert!(vm.display.pixel(14, 5));
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fe); // LOW
+        vm.step();
+
+        vm.display.set_pixel(10, 5, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fb); // SCR
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 8);
+        assert!(!vm.display.pixel(10, 5));
+        assert!(vm.display.pixel(12, 5));
+    }
+
+    #[test]
+    fn opcode_00fc() {
+        let mut vm = VM::new();
+        vm.quirks.lores_scroll_half = true;
+
+        vm.write_u16(vm.regs.pc as usize, 0x00ff); // HIGH
+        vm.step();
+
+        vm.display.set_pixel(10, 5, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fc); // SCL
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert!(!vm.display.pixel(10, 5));
+        assert!(vm.display.pixel(6, 5));
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fe); // LOW
+        vm.step();
+
+        vm.display.set_pixel(10, 5, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fc); // SCL
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 8);
+        assert!(!vm.display.pixel(10, 5));
+        assert!(vm.display.pixel(8, 5));
+    }
+
+    #[test]
+    fn opcode_00dn() {
+        let mut vm = VM::new();
+        vm.quirks.lores_scroll_half = true;
+
+        vm.write_u16(vm.regs.pc as usize, 0x00ff); // HIGH
+        vm.step();
+
+        vm.display.set_pixel(10, 10, true);
+        vm.display.set_pixel(11, 63, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00d3); // SCU 3
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert!(!vm.display.pixel(10, 10));
+        assert!(vm.display.pixel(10, 7));
+        assert!(!vm.display.pixel(11, 63));
+        assert!(vm.display.pixel(11, 60));
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fe); // LOW
+        vm.step();
+
+        vm.display.set_pixel(10, 10, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00d4); // SCU 4
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 8);
+        assert!(!vm.display.pixel(10, 10));
+        assert!(vm.display.pixel(10, 8));
+    }
+
+    #[test]
+    fn opcode_00dn_planes() {
+        let mut vm = VM::new();
+        vm.quirks.lores_scroll_half = true;
+
+        vm.display.select_planes(0b11);
+        vm.display.set_pixel(3, 10, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf201); // PLANE 2
+        vm.step();
+        vm.write_u16(vm.regs.pc as usize, 0x00d2); // SCU 2
+        vm.step();
+
+        assert_eq!(vm.display.planes(3, 10), 0b01);
+        assert_eq!(vm.display.planes(3, 9), 0b10);
+    }
+
+    #[test]
+    fn opcode_00e0() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.display.set_pixel(0, 0, true);
+        vm.display.set_pixel(10, 20, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00e0); // CLS
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert!(!vm.display.pixel(0, 0));
+        assert!(!vm.display.pixel(10, 20));
+    }
+
+    #[test]
+    fn opcode_00fd() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert!(!vm.is_halted());
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fd); // EXIT
+        vm.write_u16(vm.regs.pc as usize + 2, 0x6123); // LD V1, 0x23
+        vm.step();
+
+        assert!(vm.is_halted());
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.step();
+
+        assert!(vm.is_halted());
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[1], 0);
+
+        vm.reset();
+
+        assert!(!vm.is_halted());
+    }
+
+    #[test]
+    fn opcode_00fe_00ff() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.display.resolution(), Resolution::Lores);
+        assert_eq!(vm.display.width(), 64);
+        assert_eq!(vm.display.height(), 32);
+
+        vm.display.set_pixel(1, 1, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00ff); // HIGH
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.display.resolution(), Resolution::Hires);
+        assert_eq!(vm.display.width(), 128);
+        assert_eq!(vm.display.height(), 64);
+        assert!(!vm.display.pixel(1, 1));
+
+        vm.display.set_pixel(100, 50, true);
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fe); // LOW
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.display.resolution(), Resolution::Lores);
+        assert_eq!(vm.display.width(), 64);
+        assert_eq!(vm.display.height(), 32);
+        assert!(!vm.display.pixel(1, 1));
+    }
+
+    #[test]
+    fn opcode_1nnn() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.write_u16(vm.regs.pc as usize, 0x1123); // JP 0x123
+        vm.step();
+
+        assert_eq!(vm.regs.pc, 0x0123);
+    }
+
+    #[test]
+    fn opcode_2nnn() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+
+        vm.write_u16(vm.regs.pc as usize, 0x2123); // CALL 0x123
+        vm.step();
+
+        assert_eq!(vm.regs.pc, 0x0123);
+        assert_eq!(vm.regs.sp, (STACK_SIZE - 1) as u16);
+        assert_eq!(vm.stack[vm.regs.sp as usize], INITIAL_PC + 2);
+    }
+
+    #[test]
+    fn opcode_2nnn_overflow() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0x2200); // CALL 0x200
+
+        for depth in 1..=STACK_SIZE {
+            vm.step();
+
+            assert_eq!(vm.regs.sp as usize, STACK_SIZE - depth);
+            assert_eq!(vm.status(), Status::Running);
+        }
+
+        assert!(vm.stack.iter().all(|&address| address == INITIAL_PC + 2));
+
+        vm.step();
+
+        assert_eq!(vm.fault(), Some(VmError::StackOverflow));
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.sp, 0);
+
+        vm.step();
+
+        assert_eq!(vm.cycles(), STACK_SIZE as u64 + 1);
+
+        vm.write_u16(0x202, 0x00ee); // RET
+        vm.reset_keeping_rom();
+        vm.regs.pc = 0x202;
+        vm.regs.sp = 0;
+        vm.stack.fill(0x202);
+
+        assert_eq!(
+            vm.run_until_halt(100),
+            RunResult::Fault(VmError::StackUnderflow)
+        );
+        assert_eq!(vm.cycles(), STACK_SIZE as u64 + 1);
+    }
+
+    #[test]
+    fn opcode_3xkk() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.write_u16(vm.regs.pc as usize, 0x3123); // SE V1, 0x23
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+
+        vm.regs.v[1] = 0x23;
+
+        vm.write_u16(vm.regs.pc as usize, 0x3123); // SE V1, 0x23
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+    }
+
+    #[test]
+    fn opcode_4xkk() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.write_u16(vm.regs.pc as usize, 0x4123); // SNE V1, 0x23
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+
+        vm.regs.v[1] = 0x23;
+
+        vm.write_u16(vm.regs.pc as usize, 0x4123); // SNE V1, 0x23
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+    }
+
+    #[test]
+    fn opcode_5xy0() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.write_u16(vm.regs.pc as usize, 0x5120); // SE V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+
+        vm.regs.v[1] = 0x23;
+
+        vm.write_u16(vm.regs.pc as usize, 0x5120); // SE V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+    }
+
+    #[test]
+    fn opcode_6xkk() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[1], 0x00);
+
+        vm.write_u16(vm.regs.pc as usize, 0x6123); // LD V1, 0x23
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[1], 0x23);
+    }
+
+    #[test]
+    fn opcode_7xkk() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[1], 0x00);
+
+        vm.regs.v[1] = 0x01;
+        vm.write_u16(vm.regs.pc as usize, 0x7123); // ADD V1, 0x23
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[1], 0x24);
+    }
+
+    #[test]
+    fn opcode_8xy0() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[1], 0x00);
+        assert_eq!(vm.regs.v[2], 0x00);
+
+        vm.regs.v[2] = 0x01;
+        vm.write_u16(vm.regs.pc as usize, 0x8120); // LD V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[1], 0x01);
+        assert_eq!(vm.regs.v[2], 0x01);
+    }
+
+    #[test]
+    fn opcode_8xy1() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[1], 0x00);
+        assert_eq!(vm.regs.v[2], 0x00);
+
+        vm.regs.v[1] = 0x12;
+        vm.regs.v[2] = 0x23;
+        vm.write_u16(vm.regs.pc as usize, 0x8121); // OR V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[1], 0x33);
+        assert_eq!(vm.regs.v[2], 0x23);
+    }
+
+    #[test]
+    fn opcode_8xy2() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[1], 0x00);
+        assert_eq!(vm.regs.v[2], 0x00);
+
+        vm.regs.v[1] = 0x12;
+        vm.regs.v[2] = 0x23;
+        vm.write_u16(vm.regs.pc as usize, 0x8122); // AND V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[1], 0x02);
+        assert_eq!(vm.regs.v[2], 0x23);
+    }
+
+    #[test]
+    fn opcode_8xy3() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[1], 0x00);
+        assert_eq!(vm.regs.v[2], 0x00);
+
+        vm.regs.v[1] = 0x12;
+        vm.regs.v[2] = 0x23;
+        vm.write_u16(vm.regs.pc as usize, 0x8123); // XOR V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[1], 0x31);
+        assert_eq!(vm.regs.v[2], 0x23);
+    }
+
+    #[test]
+    fn opcode_8xy4() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0x1], 0);
+        assert_eq!(vm.regs.v[0x2], 0);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 5;
+        vm.regs.v[2] = 5;
+        vm.write_u16(vm.regs.pc as usize, 0x8124); // ADD V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0x1], 10);
+        assert_eq!(vm.regs.v[0x2], 5);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 255;
+        vm.regs.v[2] = 5;
+        vm.write_u16(vm.regs.pc as usize, 0x8124); // ADD V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.v[0x1], 4);
+        assert_eq!(vm.regs.v[0x2], 5);
+        assert_eq!(vm.regs.v[0xf], 1);
+    }
+
+    #[test]
+    fn opcode_8xy5() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0x1], 0);
+        assert_eq!(vm.regs.v[0x2], 0);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 5;
+        vm.regs.v[2] = 10;
+        vm.write_u16(vm.regs.pc as usize, 0x8125); // SUB V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0x1], 251);
+        assert_eq!(vm.regs.v[0x2], 10);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 10;
+        vm.regs.v[2] = 5;
+        vm.write_u16(vm.regs.pc as usize, 0x8125); // SUB V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.v[0x1], 5);
+        assert_eq!(vm.regs.v[0x2], 5);
+        assert_eq!(vm.regs.v[0xf], 1);
+    }
+
+    #[test]
+    fn opcode_8xy6() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0x1], 0);
+        assert_eq!(vm.regs.v[0x2], 0);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 8;
+        vm.write_u16(vm.regs.pc as usize, 0x8126); // SHR V1 {, V2}
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0x1], 4);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 9;
+        vm.write_u16(vm.regs.pc as usize, 0x8126); // SHR V1 {, V2}
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.v[0x1], 4);
+        assert_eq!(vm.regs.v[0xf], 1);
+    }
+
+    #[test]
+    fn opcode_8xy7() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0x1], 0);
+        assert_eq!(vm.regs.v[0x2], 0);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 8;
+        vm.regs.v[2] = 4;
+        vm.write_u16(vm.regs.pc as usize, 0x8127); // SUBN V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0x1], 252);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 4;
+        vm.regs.v[2] = 8;
+        vm.write_u16(vm.regs.pc as usize, 0x8127); // SUBN V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.v[0x1], 4);
+        assert_eq!(vm.regs.v[0xf], 1);
+    }
+
+    #[test]
+    fn opcode_8xye() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0x1], 0);
+        assert_eq!(vm.regs.v[0x2], 0);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 8;
+        vm.write_u16(vm.regs.pc as usize, 0x812e); // SHL V1 {, V2}
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0x1], 16);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.v[1] = 0x89;
+        vm.write_u16(vm.regs.pc as usize, 0x812e); // SHL V1 {, V2}
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.v[0x1], 0x12);
+        assert_eq!(vm.regs.v[0xf], 1);
+    }
+
+    #[test]
+    fn opcode_9xy0() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.write_u16(vm.regs.pc as usize, 0x9120); // SNE V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+
+        vm.regs.v[1] = 0x23;
+
+        vm.write_u16(vm.regs.pc as usize, 0x9120); // SNE V1, V2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+    }
+
+    #[test]
+    fn opcode_annn() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.i, 0);
+
+        vm.write_u16(vm.regs.pc as usize, 0xa123); // LD I, 0x123
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.i, 0x123);
+    }
+
+    #[test]
+    fn opcode_bnnn() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0], 0);
+
+        vm.regs.v[0] = 1;
+
+        vm.write_u16(vm.regs.pc as usize, 0xb012); // JP V0, 0x12
+        vm.step();
+
+        assert_eq!(vm.regs.pc, 0x13);
+        assert_eq!(vm.regs.v[0], 1);
+    }
+
+    #[test]
+    fn opcode_cxkk() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0], 0);
+
+        vm.write_u16(vm.regs.pc as usize, 0xc033); // RND V0, 0x33
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0], 0x20);
+    }
+
+    #[test]
+    fn opcode_dxyn() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        let i: usize = 0x300;
+
+        vm.regs.i = i as u16;
+        vm.memory[i] = 0b1100_0000;
+        vm.memory[i + 1] = 0b0011_0000;
+        vm.regs.v[0] = 62;
+        vm.regs.v[1] = 30;
+
+        vm.write_u16(vm.regs.pc as usize, 0xd012); // DRW V0, V1, 2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert!(vm.display.pixel(62, 30));
+        assert!(vm.display.pixel(63, 30));
+        assert!(!vm.display.pixel(62, 31));
+        assert!(!vm.display.pixel(0, 30));
+        assert!(!vm.display.pixel(0, 31));
+
+        vm.write_u16(vm.regs.pc as usize, 0xd012); // DRW V0, V1, 2
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.v[0xf], 1);
+        assert!(!vm.display.pixel(62, 30));
+        assert!(!vm.display.pixel(63, 30));
+    }
+
+    #[test]
+    fn opcode_dxyn_hires() {
+        let mut vm = VM::new();
+
+        let i: usize = 0x300;
+
+        vm.regs.i = i as u16;
+        vm.memory[i] = 0b1000_0001;
+        vm.regs.v[0] = 120;
+        vm.regs.v[1] = 63;
+
+        vm.write_u16(vm.regs.pc as usize, 0x00ff); // HIGH
+        vm.step();
+        vm.write_u16(vm.regs.pc as usize, 0xd011); // DRW V0, V1, 1
+        vm.step();
+
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert!(vm.display.pixel(120, 63));
+        assert!(vm.display.pixel(127, 63));
+
+        vm.write_u16(vm.regs.pc as usize, 0x00fe); // LOW
+        vm.step();
+        vm.write_u16(vm.regs.pc as usize, 0xd011); // DRW V0, V1, 1
+        vm.step();
+
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert!(vm.display.pixel(120 % 64, 63 % 32));
+        assert!(vm.display.pixel(127 % 64, 63 % 32));
+    }
+
+    #[test]
+    fn opcode_dxyn_dirty() {
+        let mut vm = VM::new();
+
+        vm.display.clear_dirty();
+        vm.regs.v[0] = 8;
+        vm.write_u16(0x200, 0xf029); // LD F, V0
+        vm.write_u16(0x202, 0xd005); // DRW V0, V0, 5
+        vm.step();
+
+        assert!(!vm.display.is_dirty());
+
+        vm.step();
+
+        assert!(vm.display.is_dirty());
+
+        vm.display.clear_dirty();
+
+        assert!(!vm.display.is_dirty());
+    }
+
+    #[test]
+    fn opcode_dxyn_display_wait() {
+        let mut vm = VM::new();
+
+        let program = [
+            0xa050, // LD I, 0x050
+            0xd005, // DRW V0, V0, 5
+            0x7501, // ADD V5, 0x01
+            0x1202, // JP 0x202
+        ];
+        vm.write_program(0x200, &program).unwrap();
+
+        vm.run_frame(100);
+
+        assert_eq!(vm.regs.v[5], 33);
+
+        vm.reset();
+        vm.quirks.display_wait = true;
+        let program = [
+            0xa050, // LD I, 0x050
+            0xd005, // DRW V0, V0, 5
+            0x7501, // ADD V5, 0x01
+            0x1202, // JP 0x202
+        ];
+        vm.write_program(0x200, &program).unwrap();
+
+        vm.run_frame(100);
+
+        assert_eq!(vm.regs.v[5], 1);
+        assert_eq!(vm.regs.pc, 0x202);
+        assert!(vm.display.pixel(0, 0));
+
+        for frame in 2..=5 {
+            vm.run_frame(100);
+
+            assert_eq!(vm.regs.v[5], frame);
+            assert_eq!(vm.regs.pc, 0x202);
+            assert_eq!(vm.display.pixel(0, 0), frame % 2 == 1);
+        }
+    }
+
+    #[test]
+    fn opcode_dxyn_sprite_wrap() {
+        let mut vm = VM::new();
+
+        vm.regs.v[0] = 60;
+        vm.regs.v[1] = 30;
+        vm.regs.i = FONT_START as u16;
+        vm.write_u16(vm.regs.pc as usize, 0xd015); // DRW V0, V1, 5
+        vm.step();
+
+        assert!(vm.display.pixel(60, 30));
+        assert!(vm.display.pixel(63, 31));
+        assert!(!vm.display.pixel(0, 30));
+        assert!(!vm.display.pixel(0, 0));
+        assert!(!vm.display.pixel(60, 0));
+
+        vm.reset();
+        vm.quirks.sprite_wrap = true;
+        vm.regs.v[0] = 60;
+        vm.regs.v[1] = 30;
+        vm.regs.i = FONT_START as u16;
+        vm.write_u16(vm.regs.pc as usize, 0xd015); // DRW V0, V1, 5
+        vm.step();
+
+        // "0" is 0xf0, 0x90, 0x90, 0x90, 0xf0, only the left 4 columns are set
+        assert!(vm.display.pixel(60, 30));
+        assert!(vm.display.pixel(63, 31));
+        assert!(!vm.display.pixel(0, 30));
+        assert!(vm.display.pixel(60, 0));
+        assert!(vm.display.pixel(63, 2));
+        assert!(!vm.display.pixel(61, 1));
+
+        vm.regs.v[0] = 62;
+        vm.regs.v[1] = 0;
+        vm.write_u16(vm.regs.pc as usize, 0xd015); // DRW V0, V1, 5
+        vm.step();
+
+        assert!(vm.display.pixel(0, 0));
+        assert!(vm.display.pixel(1, 4));
+        assert!(!vm.display.pixel(2, 0));
+        assert_eq!(vm.regs.v[0xf], 1);
+    }
+
+    #[test]
+    fn opcode_dxy0() {
+        let mut vm = VM::new();
+
+        let i: usize = 0x300;
+
+        vm.regs.i = i as u16;
+        vm.memory[i..i + 32].fill(0xff);
+        vm.regs.v[0] = 10;
+        vm.regs.v[1] = 5;
+
+        vm.write_u16(vm.regs.pc as usize, 0x00ff); // HIGH
+        vm.step();
+        vm.write_u16(vm.regs.pc as usize, 0xd010); // DRW V0, V1, 0
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        for y in 0..vm.display.height() {
+            for x in 0..vm.display.width() {
+                let inside = (10..26).contains(&x) && (5..21).contains(&y);
+                assert_eq!(vm.display.pixel(x, y), inside);
+            }
+        }
+
+        vm.write_u16(vm.regs.pc as usize, 0xd010); // DRW V0, V1, 0
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+        assert_eq!(vm.regs.v[0xf], 1);
+        assert!(!vm.display.pixel(10, 5));
+        assert!(!vm.display.pixel(25, 20));
+    }
+
+    #[test]
+    fn opcode_ex9e() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.regs.v[1] = 0xa;
+        vm.write_u16(vm.regs.pc as usize, 0xe19e); // SKP V1
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+
+        vm.keypad.key_down(0xa);
+        vm.write_u16(vm.regs.pc as usize, 0xe19e); // SKP V1
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+    }
+
+    #[test]
+    fn opcode_exa1() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.regs.v[1] = 0xa;
+        vm.keypad.key_down(0xa);
+        vm.write_u16(vm.regs.pc as usize, 0xe1a1); // SKNP V1
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+
+        vm.keypad.key_up(0xa);
+        vm.write_u16(vm.regs.pc as usize, 0xe1a1); // SKNP V1
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+    }
+
+    #[test]
+    fn opcode_f000() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.i, 0);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf000); // LD I, long 0x1234
+        vm.write_u16(vm.regs.pc as usize + 2, 0x1234);
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.i, 0x1234);
+    }
+
+    #[test]
+    fn opcode_fx01() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.display.selected_planes(), 1);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf301); // PLANE 3
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.display.selected_planes(), 3);
+    }
+
+    #[test]
+    fn opcode_dxyn_planes() {
+        let mut vm = VM::new();
+
+        let i: usize = 0x300;
+
+        vm.regs.i = i as u16;
+        vm.memory[i] = 0b1000_0000;
+        vm.memory[i + 1] = 0b0100_0000;
+
+        vm.write_u16(vm.regs.pc as usize, 0xf201); // PLANE 2
+        vm.step();
+        vm.write_u16(vm.regs.pc as usize, 0xd001); // DRW V0, V0, 1
+        vm.step();
+
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert_eq!(vm.display.planes(0, 0), 0b10);
+        assert_eq!(vm.display.planes(1, 0), 0);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf301); // PLANE 3
+        vm.step();
+        vm.write_u16(vm.regs.pc as usize, 0xd001); // DRW V0, V0, 1
+        vm.step();
+
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert_eq!(vm.display.planes(0, 0), 0b11);
+        assert_eq!(vm.display.planes(1, 0), 0b10);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf101); // PLANE 1
+        vm.step();
+        vm.write_u16(vm.regs.pc as usize, 0xd001); // DRW V0, V0, 1
+        vm.step();
+
+        assert_eq!(vm.regs.v[0xf], 1);
+        assert_eq!(vm.display.planes(0, 0), 0b10);
+        assert_eq!(vm.display.planes(1, 0), 0b10);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf001); // PLANE 0
+        vm.step();
+        vm.write_u16(vm.regs.pc as usize, 0xd001); // DRW V0, V0, 1
+        vm.step();
+
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert_eq!(vm.display.planes(0, 0), 0b10);
+        assert_eq!(vm.display.planes(1, 0), 0b10);
+    }
+
+    #[test]
+    fn opcode_f002() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.audio_buffer, [0; AUDIO_BUFFER_SIZE]);
+
+        let i: usize = 0x300;
+        let pattern: [u8; AUDIO_BUFFER_SIZE] = [
+            0x00, 0xff, 0x00, 0xff, 0x0f, 0xf0, 0x0f, 0xf0, 0xaa, 0x55, 0xaa, 0x55, 0x01, 0x02,
+            0x03, 0x04,
+        ];
+
+        vm.regs.i = i as u16;
+        vm.memory[i..i + AUDIO_BUFFER_SIZE].copy_from_slice(&pattern);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf002); // AUDIO
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.i, i as u16);
+        assert_eq!(vm.audio_buffer, pattern);
+
+        vm.reset();
+
+        assert_eq!(vm.audio_buffer, [0; AUDIO_BUFFER_SIZE]);
+    }
+
+    #[test]
+    fn opcode_fx07() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0], 0);
+        assert_eq!(vm.regs.delay_timer, 0);
+
+        vm.regs.delay_timer = 1;
+        vm.write_u16(vm.regs.pc as usize, 0xf007); // LD V0, DT
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0], 1);
+        assert_eq!(vm.regs.delay_timer, 1);
+    }
+
+    #[test]
+    fn opcode_fx0a() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf30a); // LD V3, K
+
+        for _ in 0..3 {
+            let result = vm.step();
+
+            assert_eq!(result.status, StepStatus::WaitingForKey);
+            assert_eq!(result.instruction, Some(Instruction::LdVxK(3)));
+            assert_eq!(vm.regs.pc, INITIAL_PC);
+        }
+
+        vm.keypad.key_down(0x9);
+
+        assert_eq!(vm.step().status, StepStatus::Ok);
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[3], 0x9);
+    }
+
+    #[test]
+    fn opcode_fx15() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0], 0);
+        assert_eq!(vm.regs.delay_timer, 0);
+
+        vm.regs.v[0] = 1;
+        vm.write_u16(vm.regs.pc as usize, 0xf015); // LD DT, V0
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0], 1);
+        assert_eq!(vm.regs.delay_timer, 1);
+    }
+
+    #[test]
+    fn opcode_fx18() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0], 0);
+        assert_eq!(vm.regs.sound_timer, 0);
+
+        vm.regs.v[0] = 1;
+        vm.write_u16(vm.regs.pc as usize, 0xf018); // LD ST, V0
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.v[0], 1);
+        assert_eq!(vm.regs.sound_timer, 1);
+    }
+
+    #[test]
+    fn opcode_fx1e() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.v[0], 0);
+        assert_eq!(vm.regs.i, 0);
+
+        vm.regs.i = 1;
+        vm.regs.v[0] = 1;
+        vm.write_u16(vm.regs.pc as usize, 0xf01e); // ADD I, V0
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.i, 2);
+        assert_eq!(vm.regs.v[0], 1);
+    }
+
+    #[test]
+    fn opcode_fx29() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.regs.v[0] = 0x0a;
+        vm.write_u16(vm.regs.pc as usize, 0xf029); // LD F, V0
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.i as usize, FONT_START + 0x0a * FONT_GLYPH_SIZE);
+        assert_eq!(vm.memory[vm.regs.i as usize], 0xf0);
+        assert_eq!(vm.memory[vm.regs.i as usize + 4], 0x90);
+    }
+
+    #[test]
+    fn opcode_fx29_draw() {
+        let mut vm = VM::new();
+
+        vm.regs.v[0] = 0;
+        vm.write_u16(0x200, 0xf029); // LD F, V0
+        vm.write_u16(0x202, 0xd005); // DRW V0, V0, 5
+        vm.step();
+        vm.step();
+
+        let glyph = ["████", "█  █", "█  █", "█  █", "████"];
+        let mut expected = String::new();
+
+        for y in 0..vm.display.height() {
+            let row = glyph.get(y).copied().unwrap_or("");
+            expected.push_str(row);
+            expected.push_str(&" ".repeat(vm.display.width() - row.chars().count()));
+            expected.push('\n');
+        }
+
+        assert_eq!(vm.display.to_ascii(), expected);
+    }
+
+    #[test]
+    fn opcode_fx30() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.regs.v[0] = 0x17;
+        vm.write_u16(vm.regs.pc as usize, 0xf030); // LD HF, V0
+        vm.step();
+
+        let i = vm.regs.i as usize;
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(i, BIG_FONT_START + 7 * BIG_FONT_GLYPH_SIZE);
+        assert_eq!(
+            vm.memory[i..i + BIG_FONT_GLYPH_SIZE],
+            [0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18]
+        );
+    }
+
+    #[test]
+    fn opcode_fx33() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+
+        vm.regs.v[1] = 234;
+        vm.regs.i = 0x300;
+        vm.write_u16(vm.regs.pc as usize, 0xf133); // LD B, V1
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.i, 0x300);
+        assert_eq!(vm.memory[0x300..0x303], [2, 3, 4]);
+
+        vm.regs.v[1] = 7;
+        vm.write_u16(vm.regs.pc as usize, 0xf133); // LD B, V1
+        vm.step();
+
+        assert_eq!(vm.memory[0x300..0x303], [0, 0, 7]);
+    }
+
+    #[test]
+    fn opcode_fx3a() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.pitch, DEFAULT_PITCH);
+        assert_eq!(vm.audio_frequency(), 4000.0);
+
+        vm.regs.v[1] = 112;
+        vm.write_u16(vm.regs.pc as usize, 0xf13a); // PITCH V1
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.pitch, 112);
+        assert_eq!(vm.audio_frequency(), 8000.0);
+
+        vm.regs.v[1] = 16;
+        vm.write_u16(vm.regs.pc as usize, 0xf13a); // PITCH V1
+        vm.step();
+
+        assert_eq!(vm.pitch, 16);
+        assert_eq!(vm.audio_frequency(), 2000.0);
+
+        vm.reset();
+
+        assert_eq!(vm.pitch, DEFAULT_PITCH);
+    }
+
+    #[test]
+    fn opcode_fx55() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.i, 0);
+
+        let i: usize = 10;
+
+        vm.regs.i = i as u16;
+        vm.regs.v[0] = 1;
+        vm.regs.v[1] = 2;
+        vm.regs.v[2] = 3;
+
+        vm.write_u16(vm.regs.pc as usize, 0xf355); // LD [I], Vx
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.i, i as u16);
+        assert_eq!(vm.regs.v[0], 1);
+        assert_eq!(vm.regs.v[1], 2);
+        assert_eq!(vm.regs.v[2], 3);
+        assert_eq!(vm.regs.v[3], 0);
+
+        assert_eq!(vm.memory[i], 1);
+        assert_eq!(vm.memory[i + 1], 2);
+        assert_eq!(vm.memory[i + 2], 3);
+        assert_eq!(vm.memory[i + 3], 0);
+    }
+
+    #[test]
+    fn opcode_fx65() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.i, 0);
+        assert_eq!(vm.regs.v[0], 0);
+        assert_eq!(vm.regs.v[1], 0);
+        assert_eq!(vm.regs.v[2], 0);
+        assert_eq!(vm.regs.v[3], 0);
+
+        let i: usize = 10;
+
+        vm.regs.i = i as u16;
+
+        vm.memory[i] = 1;
+        vm.memory[i + 1] = 2;
+        vm.memory[i + 2] = 3;
+        vm.memory[i + 3] = 0;
+
+        vm.write_u16(vm.regs.pc as usize, 0xf365); // LD Vx, [I]
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.i, i as u16);
+        assert_eq!(vm.regs.v[0], 1);
+        assert_eq!(vm.regs.v[1], 2);
+        assert_eq!(vm.regs.v[2], 3);
+        assert_eq!(vm.regs.v[3], 0);
+
+        assert_eq!(vm.memory[i], 1);
+        assert_eq!(vm.memory[i + 1], 2);
+        assert_eq!(vm.memory[i + 2], 3);
+        assert_eq!(vm.memory[i + 3], 0);
+    }
+
+    #[test]
+    fn opcode_fx75_fx85() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.rpl, [0; RPL_SIZE]);
+
+        for i in 0..V_REG_SIZE {
+            vm.regs.v[i] = i as u8 + 1;
+        }
+
+        vm.write_u16(vm.regs.pc as usize, 0xff75); // LD R, VF
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.rpl, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        vm.regs.v.fill(0);
+
+        vm.write_u16(vm.regs.pc as usize, 0xf385); // LD V3, R
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.v[..4], [1, 2, 3, 4]);
+        assert_eq!(vm.regs.v[4..], [0; V_REG_SIZE - 4]);
+
+        vm.write_u16(vm.regs.pc as usize, 0xff85); // LD VF, R
+        vm.step();
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+        assert_eq!(vm.regs.v[..RPL_SIZE], vm.rpl);
+        assert_eq!(vm.regs.v[RPL_SIZE..], [0; V_REG_SIZE - RPL_SIZE]);
+    }
+
+    #[test]
+    fn rpl_reset() {
+        let mut vm = VM::new();
+
+        vm.rpl.fill(1);
+        vm.reset();
+
+        assert_eq!(vm.rpl, [0; RPL_SIZE]);
+
+        vm.rpl.fill(1);
+        vm.keep_rpl_on_reset = true;
+        vm.reset();
+
+        assert_eq!(vm.rpl, [1; RPL_SIZE]);
+    }
+
+    #[test]
+    fn run_until_halt() {
+        let mut vm = VM::new();
+
+        let program = [
+            0x6000, // LD V0, 0x00
+            0x7001, // ADD V0, 0x01
+            0x3005, // SE V0, 0x05
+            0x1202, // JP 0x202
+            0x1208, // JP 0x208
+        ];
+        vm.write_program(0x200, &program).unwrap();
+
+        assert_eq!(vm.run_until_halt(1000), RunResult::SelfJump);
+        assert_eq!(vm.regs.pc, 0x208);
+        assert_eq!(vm.regs.v[0], 5);
+
+        vm.reset();
+        vm.write_u16(0x200, 0x1202); // JP 0x202
+        vm.write_u16(0x202, 0x1200); // JP 0x200
+
+        assert_eq!(vm.run_until_halt(100), RunResult::CycleLimitReached);
+
+        vm.reset();
+        vm.write_u16(0x200, 0x00fd); // EXIT
+
+        assert_eq!(vm.run_until_halt(100), RunResult::Halted);
+        assert_eq!(vm.run_until_halt(100), RunResult::Halted);
+    }
+
+    #[test]
+    fn self_jump_status() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.status(), Status::Running);
+
+        vm.write_u16(0x200, 0x1202); // JP 0x202
+        vm.write_u16(0x202, 0x1202); // JP 0x202
+        vm.step();
+
+        assert_eq!(vm.status(), Status::Running);
+
+        vm.step();
+
+        assert_eq!(vm.status(), Status::SelfJump);
+        assert_eq!(vm.regs.pc, 0x202);
+
+        vm.opcode_stats_enabled = true;
+        vm.regs.delay_timer = 5;
+        vm.run_frame(10);
+
+        assert_eq!(vm.opcode_stats()[0x1], 0);
+        assert_eq!(vm.regs.delay_timer, 4);
+        assert_eq!(vm.status(), Status::SelfJump);
+
+        vm.regs.pc = INITIAL_PC;
+        vm.run_frame(10);
+
+        assert_eq!(vm.opcode_stats()[0x1], 2);
+        assert_eq!(vm.status(), Status::SelfJump);
+
+        vm.write_u16(0x200, 0x00fd); // EXIT
+        vm.regs.pc = INITIAL_PC;
+        vm.step();
+
+        assert_eq!(vm.status(), Status::Halted);
+    }
+
+    #[test]
+    fn default() {
+        let vm = VM::default();
+
+        assert_eq!(vm.memory_size(), MEMORY_SIZE);
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_memory_cleared(&vm);
+    }
+
+    #[test]
+    fn clone_independence() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0xc0ff); // RND V0, 0xff
+        vm.write_u16(0x202, 0xd015); // DRW V0, V1, 5
+        vm.regs.v[1] = 3;
+        vm.regs.i = 0x050;
+        vm.regs.delay_timer = 7;
+        vm.step();
+
+        let mut clone = vm.clone();
+
+        assert_eq!(clone.memory, vm.memory);
+        assert_eq!(clone.regs.v, vm.regs.v);
+        assert_eq!(clone.regs.pc, vm.regs.pc);
+
+        vm.step();
+        clone.step();
+
+        assert_eq!(clone.display.to_ascii(), vm.display.to_ascii());
+
+        clone.write_u8(0x300, 0xaa);
+        clone.regs.v[1] = 9;
+        clone.regs.delay_timer = 0;
+        clone.stack[0] = 0x400;
+        clone.display.clear();
+        clone.rpl[0] = 1;
+
+        assert_eq!(vm.read_u8(0x300), 0);
+        assert_eq!(vm.regs.v[1], 3);
+        assert_eq!(vm.regs.delay_timer, 7);
+        assert_eq!(vm.stack[0], 0);
+        assert!(vm.display.to_ascii().contains('█'));
+        assert!(!clone.display.to_ascii().contains('█'));
+        assert_eq!(vm.rpl[0], 0);
+
+        vm.write_u16(0x204, 0xc0ff); // RND V0, 0xff
+        clone.write_u16(0x204, 0xc0ff); // RND V0, 0xff
+        vm.step();
+        clone.step();
+
+        assert_eq!(clone.regs.v[0], vm.regs.v[0]);
+    }
+
+    #[test]
+    fn register_accessors() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.pc(), INITIAL_PC);
+        assert_eq!(vm.i(), 0);
+
+        for x in 0..V_REG_SIZE as u8 {
+            vm.set_reg(x, x * 2);
+        }
+
+        assert_eq!(vm.regs.v[0x0], 0);
+        assert_eq!(vm.regs.v[0xf], 30);
+        assert_eq!(vm.reg(0x7), 14);
+
+        vm.write_u16(0x200, 0x8ab4); // ADD VA, VB
+        vm.step();
+
+        assert_eq!(vm.reg(0xa), 42);
+        assert_eq!(vm.reg(0xf), 0);
+        assert_eq!(vm.pc(), 0x202);
+
+        vm.set_pc(0x300);
+        vm.set_i(0x050);
+
+        assert_eq!(vm.regs.pc, 0x300);
+        assert_eq!(vm.regs.i, 0x050);
+    }
+
+    #[test]
+    fn register_accessors_out_of_range() {
+        let mut vm = VM::new();
+
+        vm.set_reg(0xff, 1);
+
+        assert_eq!(vm.regs.v[0xf], 1);
+        assert_eq!(vm.reg(0x1f), 1);
+        assert_eq!(vm.reg(0x10), vm.reg(0x0));
+    }
+
+    #[test]
+    fn reset_keeping_rom() {
+        let mut vm = VM::new();
+        let rom = [0x60, 0x12, 0xa3, 0x00, 0xf0, 0x55, 0x12, 0x06];
+
+        vm.memory[0x200..0x208].copy_from_slice(&rom);
+        vm.memory[0x000] = 0xaa;
+        vm.memory[0xfff] = 0xbb;
+
+        for _ in 0..4 {
+            vm.step();
+        }
+
+        vm.regs.delay_timer = 10;
+        vm.regs.sound_timer = 20;
+        vm.stack[0] = 0x300;
+        vm.regs.sp = 0;
+        vm.display.set_pixel(1, 1, true);
+
+        assert_eq!(vm.read_u8(0x300), 0x12);
+
+        vm.reset_keeping_rom();
+
+        assert_eq!(&vm.memory[0x200..0x208], &rom);
+        assert_eq!(vm.memory[0x300], 0x12);
+        assert_eq!(vm.memory[0xfff], 0xbb);
+        assert_eq!(vm.memory[0x000], 0);
+        assert_eq!(&vm.memory[FONT_START..FONT_START + FONT.len()], &FONT);
+        assert_eq!(
+            &vm.memory[BIG_FONT_START..BIG_FONT_START + BIG_FONT.len()],
+            &BIG_FONT
+        );
+        assert_eq!(vm.regs.v, [0; V_REG_SIZE]);
+        assert_eq!(vm.regs.i, 0);
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+        assert_eq!(vm.regs.delay_timer, 0);
+        assert_eq!(vm.regs.sound_timer, 0);
+        assert_eq!(vm.stack, [0; STACK_SIZE]);
+        assert!(!vm.display.pixel(1, 1));
+
+        vm.step();
+
+        assert_eq!(vm.regs.v[0], 0x12);
+    }
+
+    #[test]
+    fn display_dump() {
+        let mut vm = VM::new();
+
+        let program = [
+            0x6012, // LD V0, 0x12
+            0x6fab, // LD VF, 0xab
+            0xa300, // LD I, 0x300
+            0xf015, // LD DT, V0
+            0xff18, // LD ST, VF
+        ];
+        vm.write_program(0x200, &program).unwrap();
+
+        for _ in 0..5 {
+            vm.step();
+        }
+
+        let dump = vm.to_string();
+
+        assert_eq!(
+            dump,
+            "PC=0x020a I=0x0300 SP=16 V=[12 00 00 00 00 00 00 00 00 00 00 00 00 00 00 ab] DT=12 ST=ab"
+        );
+        assert!(!dump.contains('\n'));
+    }
+
+    #[test]
+    fn peek() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0xd125); // DRW V1, V2, 5
+        vm.write_u16(0x202, 0xe1ff); // invalid
+
+        assert_eq!(vm.peek_opcode(), 0xd125);
+        assert_eq!(vm.peek_instruction(), Ok(Instruction::Drw(1, 2, 5)));
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert!(!vm.display.is_dirty());
+
+        vm.regs.pc = 0x202;
+
+        assert_eq!(vm.peek_opcode(), 0xe1ff);
+        assert_eq!(vm.peek_instruction(), Err(VmError::InvalidOpcode(0xe1ff)));
+        assert_eq!(vm.regs.pc, 0x202);
+
+        vm.write_u8(0xfff, 0x12);
+        vm.write_u8(0x000, 0x34);
+        vm.regs.pc = 0xfff;
+
+        assert_eq!(vm.peek_opcode(), 0x1234);
+        assert_eq!(vm.peek_instruction(), Ok(Instruction::Jp(0x234)));
+    }
+
+    #[test]
+    fn load_rom_bytes() {
+        let mut vm = VM::new();
+
+        vm.regs.v[0] = 0xff;
+        vm.load_rom_bytes(&[0x60, 0x12, 0x70, 0x01, 0x12, 0x04])
+            .unwrap();
+
+        assert_eq!(vm.regs.v[0], 0);
+        assert_eq!(vm.read_u16(0x200), 0x6012);
+
+        for _ in 0..3 {
+            vm.step();
+        }
+
+        assert_eq!(vm.regs.v[0], 0x13);
+        assert_eq!(vm.status(), Status::SelfJump);
+    }
+
+    #[test]
+    fn cycles() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0x1202); // JP 0x202
+        vm.write_u16(0x202, 0x00fd); // EXIT
+
+        assert_eq!(vm.cycles(), 0);
+
+        vm.step();
+        vm.step();
+        vm.step();
+
+        assert_eq!(vm.cycles(), 2);
+
+        vm.reset();
+
+        assert_eq!(vm.cycles(), 0);
+    }
+
+    #[test]
+    fn with_seed() {
+        let mut vm = VM::with_seed(0x1234);
+        let mut other = VM::with_seed(0x1234);
+        let mut different = VM::with_seed(0x4321);
+
+        for vm in [&mut vm, &mut other, &mut different] {
+            let program = [
+                0xc0ff, // RND V0, 0xff
+                0xc1ff, // RND V1, 0xff
+                0x8014, // ADD V0, V1
+                0xc30f, // RND V3, 0x0f
+                0x1200, // JP 0x200
+            ];
+            vm.write_program(0x200, &program).unwrap();
+        }
+
+        let mut diverged = false;
+
+        for _ in 0..500 {
+            vm.step();
+            other.step();
+            different.step();
+
+            assert_eq!(vm.regs.v, other.regs.v);
+            assert_eq!(vm.regs.pc, other.regs.pc);
+
+            diverged |= vm.regs.v != different.regs.v;
+        }
+
+        assert!(diverged);
+    }
+
+    #[test]
+    fn memory_hook() {
+        use alloc::sync::Arc;
+        use std::sync::Mutex;
+
+        let mut vm = VM::new();
+        let accesses = Arc::new(Mutex::new(Vec::new()));
+        let log = accesses.clone();
+
+        vm.regs.v[2] = 159;
+        vm.regs.i = 0x300;
+        vm.write_u16(0x200, 0xf233); // LD B, V2
+        vm.set_memory_hook(Box::new(move |access| log.lock().unwrap().push(access)));
+        vm.step();
+
+        let write = |address, value| MemAccess {
+            address,
+            value,
+            kind: AccessKind::Write,
+        };
+
+        assert_eq!(
+            accesses.lock().unwrap()[..],
+            [
+                MemAccess {
+                    address: 0x200,
+                    value: 0xf2,
+                    kind: AccessKind::Read
+                },
+                MemAccess {
+                    address: 0x201,
+                    value: 0x33,
+                    kind: AccessKind::Read
+                },
+                write(0x300, 1),
+                write(0x301, 5),
+                write(0x302, 9),
+            ]
+        );
+
+        let clone = vm.clone();
+        clone.read_u8(0x300);
+
+        assert_eq!(accesses.lock().unwrap().len(), 5);
+
+        vm.clear_memory_hook();
+        vm.read_u8(0x300);
+
+        assert_eq!(accesses.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn validate_rom() {
+        let vm = VM::new();
+
+        assert_eq!(vm.validate_rom(&[]), Err(VmError::EmptyRom));
+        assert_eq!(
+            vm.validate_rom(&[0x60, 0x12, 0x00]),
+            Err(VmError::OddRomLength(3))
+        );
+        assert_eq!(
+            vm.validate_rom(&[0; MEMORY_SIZE - 0x200 + 2]),
+            Ok(RomInfo {
+                size: MEMORY_SIZE - 0x200 + 2,
+                first_opcode: 0x0000,
+                fits: false,
+                free: 0,
+            })
+        );
+        assert_eq!(
+            vm.validate_rom(&[0x60, 0x12, 0x12, 0x02]),
+            Ok(RomInfo {
+                size: 4,
+                first_opcode: 0x6012,
+                fits: true,
+                free: MEMORY_SIZE - 0x200 - 4,
+            })
+        );
+        assert_eq!(vm.validate_rom(&[0; MEMORY_SIZE - 0x200]).unwrap().free, 0);
+        assert!(vm.validate_rom(&[0; MEMORY_SIZE - 0x200]).unwrap().fits);
+
+        let vm = VM::with_memory_size(XO_MEMORY_SIZE);
+
+        assert!(vm.validate_rom(&[0; MEMORY_SIZE]).unwrap().fits);
+    }
+
+    #[test]
+    fn rom_hash() {
+        let mut vm = VM::new();
+        let empty = vm.rom_hash();
+
+        vm.load_rom_bytes(&[0x60, 0x12, 0x12, 0x02]).unwrap();
+
+        let hash = vm.rom_hash();
+
+        assert_ne!(hash, empty);
+
+        vm.load_rom_bytes(&[0x60, 0x13, 0x12, 0x02]).unwrap();
+
+        assert_ne!(vm.rom_hash(), hash);
+
+        vm.load_rom_bytes(&[0x60, 0x12, 0x12, 0x02]).unwrap();
+        vm.run_frame(10);
+        vm.regs.v[3] = 0x33;
+
+        assert_eq!(vm.rom_hash(), hash);
+
+        vm.reset_keeping_rom();
+
+        assert_eq!(vm.rom_hash(), hash);
+
+        vm.load_rom_bytes(&[0x60, 0x12, 0x12, 0x02, 0x00, 0x00])
+            .unwrap();
+
+        assert_ne!(vm.rom_hash(), hash);
+
+        vm.reset();
+
+        assert_eq!(vm.rom_hash(), empty);
+    }
+
+    #[test]
+    fn step_result() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0x6123); // LD V1, 0x23
+        vm.write_u16(0x202, 0xd125); // DRW V1, V2, 5
+        vm.write_u16(0x204, 0x00fd); // EXIT
+
+        for pc in [0x200, 0x202] {
+            let instruction = vm.peek_instruction().unwrap();
+
+            assert_eq!(
+                vm.step(),
+                StepResult {
+                    instruction: Some(instruction),
+                    pc,
+                    status: StepStatus::Ok,
+                    mnemonic: None
+                }
+            );
+        }
+
+        assert_eq!(
+            vm.step(),
+            StepResult {
+                instruction: Some(Instruction::Exit),
+                pc: 0x204,
+                status: StepStatus::Halted,
+                mnemonic: None
+            }
+        );
+        assert_eq!(
+            vm.step(),
+            StepResult {
+                instruction: None,
+                pc: 0x204,
+                status: StepStatus::Halted,
+                mnemonic: None
+            }
+        );
+
+        vm.reset();
+        vm.write_u16(0x200, 0x00ee); // RET
+
+        assert_eq!(vm.step().status, StepStatus::Fault(VmError::StackUnderflow));
+        assert_eq!(vm.step().instruction, None);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0x7001); // ADD V0, 0x01
+        vm.write_u16(0x202, 0x7101); // ADD V1, 0x01
+        vm.write_u16(0x204, 0x1200); // JP 0x200
+        vm.add_breakpoint(0x202);
+
+        assert!(vm.has_breakpoint(0x202));
+        assert_eq!(vm.step().status, StepStatus::Ok);
+
+        let result = vm.step();
+
+        assert_eq!(result.status, StepStatus::BreakpointHit);
+        assert_eq!(result.pc, 0x202);
+        assert_eq!(result.instruction, None);
+        assert_eq!(vm.regs.v[1], 0);
+
+        assert_eq!(vm.step().instruction, Some(Instruction::AddByte(1, 1)));
+        assert_eq!(vm.regs.v[1], 1);
+
+        vm.run_frame(100);
+
+        assert_eq!(vm.regs.pc, 0x202);
+        assert_eq!(vm.regs.v[0], 2);
+        assert_eq!(vm.regs.v[1], 1);
+
+        assert_eq!(vm.run_until_halt(100), RunResult::BreakpointHit);
+        assert_eq!(vm.regs.v[1], 2);
+        assert_eq!(vm.regs.pc, 0x202);
+
+        vm.remove_breakpoint(0x202);
+        vm.add_breakpoint(0x204);
+        vm.clear_breakpoints();
+        vm.run_frame(9);
+
+        assert_eq!(vm.regs.v[1], 5);
+    }
+
+    #[test]
+    fn disassemble_rom() {
+        use alloc::string::ToString;
+
+        let mut vm = VM::new();
+
+        vm.load_rom_bytes(&[0x61, 0x05, 0xa2, 0x08, 0xd0, 0x11, 0x12, 0x06, 0xe5, 0x00])
+            .unwrap();
+
+        assert_eq!(
+            vm.disassemble_rom(0x200, 0x20a),
+            [
+                (0x200, 0x6105, "LD V1, 0x05".to_string()),
+                (0x202, 0xa208, "LD I, 0x208".to_string()),
+                (0x204, 0xd011, "DRW V0, V1, 1".to_string()),
+                (0x206, 0x1206, "JP 0x206".to_string()),
+                (0x208, 0xe500, "DW 0xe500".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_traced() {
+        use alloc::string::ToString;
+
+        let mut vm = VM::new();
+
+        vm.load_rom_bytes(&[
+            0xa2, 0x06, // LD I, 0x206
+            0xd0, 0x04, // DRW V0, V0, 4
+            0x12, 0x04, // JP 0x204
+            0x00, 0xe0, 0x3c, 0x66, // sprite, 0x00e0 would decode as CLS
+        ])
+        .unwrap();
+
+        let lines = vm.disassemble_traced(INITIAL_PC);
+
+        assert_eq!(
+            lines,
+            [
+                (0x200, 0xa206, "LD I, 0x206".to_string()),
+                (0x202, 0xd004, "DRW V0, V0, 4".to_string()),
+                (0x204, 0x1204, "JP 0x204".to_string()),
+                (0x206, 0x00e0, "DW 0x00e0".to_string()),
+                (0x208, 0x3c66, "DW 0x3c66".to_string()),
+            ]
+        );
+        assert_eq!(vm.disassemble_rom(0x206, 0x208)[0].2, "CLS");
+    }
+
+    #[test]
+    fn fetch_decode_execute() {
+        let mut vm = VM::new();
+
+        vm.write_u16(0x200, 0x6a42); // LD VA, 0x42
+        vm.write_u16(0x202, 0x8aa4); // ADD VA, VA
+        vm.write_u16(0x204, 0xf1ff); // invalid
+
+        let opcode = vm.fetch();
+
+        assert_eq!(opcode, 0x6a42);
+        assert_eq!(vm.fetch(), opcode);
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.cycles(), 0);
+
+        let instruction = vm.decode(opcode).unwrap();
+
+        assert_eq!(instruction, Instruction::LdByte(0xa, 0x42));
+        assert_eq!(vm.regs.v[0xa], 0);
+
+        vm.execute(instruction);
+
+        assert_eq!(vm.regs.v[0xa], 0x42);
+        assert_eq!(vm.regs.pc, 0x202);
+        assert_eq!(vm.cycles(), 1);
+
+        vm.execute(Instruction::AddReg(0xa, 0xa));
+
+        assert_eq!(vm.regs.v[0xa], 0x84);
+        assert_eq!(vm.regs.pc, 0x204);
+        assert_eq!(vm.fetch(), 0xf1ff);
+        assert_eq!(vm.decode(vm.fetch()), Err(VmError::InvalidOpcode(0xf1ff)));
+
+        let mut stepped = VM::new();
+
+        stepped.write_u16(0x200, 0x6a42); // LD VA, 0x42
+        stepped.write_u16(0x202, 0x8aa4); // ADD VA, VA
+        stepped.step();
+        stepped.step();
+
+        assert_eq!(stepped.regs.v, vm.regs.v);
+        assert_eq!(stepped.regs.pc, vm.regs.pc);
+    }
+
+    #[test]
+    fn pc_wraparound() {
+        let mut vm = VM::new();
+
+        vm.regs.pc = 0xffe;
+        vm.write_u16(0xffe, 0x6001); // LD V0, 0x01
+        vm.write_u16(0x000, 0x6102); // LD V1, 0x02
+        vm.step();
+
+        assert_eq!(vm.regs.pc, 0x000);
+
+        vm.step();
+
+        assert_eq!(vm.regs.v[0], 1);
+        assert_eq!(vm.regs.v[1], 2);
+        assert_eq!(vm.regs.pc, 0x002);
+
+        vm.regs.pc = 0xffc;
+        vm.write_u16(0xffc, 0x3000); // SE V0, 0x00
+        vm.regs.v[0] = 0;
+        vm.step();
+
+        assert_eq!(vm.regs.pc, 0x000);
+
+        vm.regs.pc = 0xffe;
+        vm.write_u16(0xffe, 0x2400); // CALL 0x400
+        vm.step();
+
+        assert_eq!(vm.stack[vm.regs.sp as usize], 0x000);
+
+        vm.regs.v[0] = 0xff;
+        vm.write_u16(0x400, 0xbfff); // JP V0, 0xfff
+        vm.step();
+
+        assert_eq!(vm.regs.pc, 0x0fe);
+
+        let mut vm = VM::with_memory_size(XO_MEMORY_SIZE);
+
+        vm.regs.pc = 0xfffe;
+        vm.write_u16(0xfffe, 0xf000); // LD I, long
+        vm.write_u16(0x0000, 0x1234);
+        vm.step();
+
+        assert_eq!(vm.regs.i, 0x1234);
+        assert_eq!(vm.regs.pc, 0x002);
+    }
+
+    #[test]
+    fn with_entry() {
+        let mut vm = VM::new().with_entry(0x600).unwrap();
+        let rom = [0x60, 0x12, 0x16, 0x02];
+
+        assert_eq!(vm.entry(), 0x600);
+        assert_eq!(vm.regs.pc, 0x600);
+
+        vm.load_rom_bytes(&rom).unwrap();
+
+        assert_eq!(&vm.memory[0x600..0x604], &rom);
+        assert_eq!(vm.memory[0x200..0x204], [0; 4]);
+        assert_eq!(vm.regs.pc, 0x600);
+        assert_eq!(vm.validate_rom(&rom).unwrap().free, MEMORY_SIZE - 0x600 - 4);
+
+        let hash = vm.rom_hash();
+
+        assert_eq!(vm.run_until_halt(10), RunResult::SelfJump);
+        assert_eq!(vm.regs.v[0], 0x12);
+        assert_eq!(vm.regs.pc, 0x602);
+
+        vm.reset_keeping_rom();
+
+        assert_eq!(vm.regs.pc, 0x600);
+        assert_eq!(&vm.memory[0x600..0x604], &rom);
+        assert_eq!(vm.rom_hash(), hash);
+
+        vm.reset();
+
+        assert_eq!(vm.regs.pc, 0x600);
+        assert_eq!(vm.memory[0x600..0x604], [0; 4]);
+
+        let mut vm = VM::new();
+
+        vm.load_rom_bytes_at(&rom, 0x300).unwrap();
+
+        assert_eq!(&vm.memory[0x300..0x304], &rom);
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.disassemble_traced(0x300).len(), 2);
+    }
+
+    #[test]
+    fn with_entry_outside_memory() {
+        assert_eq!(
+            VM::new().with_entry(0x1000).err(),
+            Some(VmError::InvalidEntry(0x1000))
+        );
+
+        let vm = VM::with_memory_size(XO_MEMORY_SIZE)
+            .with_entry(0x1000)
+            .unwrap();
+
+        assert_eq!(vm.regs.pc, 0x1000);
+    }
+
+    #[test]
+    fn load_rom_bytes_at() {
+        let mut vm = VM::new();
+        let rom = [0x60, 0x12, 0x11, 0x02]; // LD V0, 0x12 ; JP 0x102
+
+        vm.load_rom_bytes_at(&rom, 0x100).unwrap();
+        vm.reset_keeping_rom();
+
+        assert_eq!(&vm.memory[0x100..0x104], &rom);
+        assert_eq!(vm.memory[FONT_START..FONT_START + 5], FONT[..5]);
+
+        vm.regs.v[0] = 0x42;
+
+        assert_eq!(
+            vm.load_rom_bytes_at(&[0; 5512], 0x200),
+            Err(VmError::RomTooLarge {
+                size: 5512,
+                capacity: MEMORY_SIZE - 0x200
+            })
+        );
+        assert_eq!(
+            vm.load_rom_bytes_at(&rom, 0x1000),
+            Err(VmError::OutOfBounds {
+                address: 0x1000,
+                size: 4
+            })
+        );
+        assert_eq!(vm.regs.v[0], 0x42);
+        assert_eq!(&vm.memory[0x100..0x104], &rom);
+    }
+
+    #[test]
+    fn v_registers() {
+        let mut vm = VM::new();
+
+        assert_eq!(vm.v_registers(), &[0; V_REG_SIZE]);
+
+        vm.process_opcode(0x6123); // LD V1, 0x23
+        vm.process_opcode(0x6f42); // LD VF, 0x42
+        vm.process_opcode(0x8010); // LD V0, V1
+
+        let v = vm.v_registers();
+
+        assert_eq!(v[0], 0x23);
+        assert_eq!(v[1], 0x23);
+        assert_eq!(v[0xf], 0x42);
+        assert_eq!(v, &vm.regs.v);
+    }
+
+    #[test]
+    fn draw_sprite() {
+        let mut vm = VM::new();
+        let sprite = [0b1100_0000, 0b0110_0000, 0b0011_0000];
+
+        assert!(!vm.draw_sprite(10, 5, &sprite));
+        assert!(vm.display.pixel(10, 5));
+        assert!(vm.display.pixel(11, 5));
+        assert!(!vm.display.pixel(12, 5));
+        assert!(vm.display.pixel(11, 6));
+        assert!(vm.display.pixel(12, 6));
+        assert!(vm.display.pixel(12, 7));
+        assert!(vm.display.pixel(13, 7));
+        assert!(!vm.display.pixel(10, 8));
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        assert!(vm.draw_sprite(11, 5, &[0b1000_0000]));
+        assert!(!vm.display.pixel(11, 5));
+        assert!(vm.display.pixel(10, 5));
+
+        // Same pixels as a DRW of the same data
+        let mut other = VM::new();
+
+        other.memory[0x300..0x303].copy_from_slice(&sprite);
+        other.regs.i = 0x300;
+        other.regs.v[0] = 10;
+        other.regs.v[1] = 5;
+        other.process_opcode(0xd013); // DRW V0, V1, 3
+
+        vm.draw_sprite(11, 5, &[0b1000_0000]);
+
+        assert_eq!(
+            vm.display.to_ascii_with('#', '.'),
+            other.display.to_ascii_with('#', '.')
+        );
+    }
+
+    #[test]
+    fn hexdump() {
+        let mut vm = VM::new();
+
+        vm.memory[0x300..0x310].copy_from_slice(b"Hello, CHIP-8!\x00\xff");
+
+        assert_eq!(
+            vm.hexdump(0x300, 16),
+            "0300: 48 65 6c 6c 6f 2c 20 43 48 49 50 2d 38 21 00 ff  |Hello, CHIP-8!..|\n"
+        );
+        assert_eq!(
+            vm.hexdump(0x30c, 6),
+            "030c: 38 21 00 ff 00 00                                |8!....|\n"
+        );
+        assert_eq!(vm.hexdump(0x300, 32).lines().count(), 2);
+        assert_eq!(&vm.hexdump(0xfff, 2)[..6], "0fff: ");
+        assert!(vm.hexdump(0xfff, 17).starts_with("0fff: 00 00 00 00 00 00"));
+        assert!(vm.hexdump(0x300, 0).is_empty());
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut vm = VM::with_seed(3);
+
+        // RND V0, 0xff ; LD I, 0x300 ; LD [I], V0 ; HIGH ; DRW V0, V0, 5 ; JP 0x200
+        vm.load_rom_bytes(&[
+            0xc0, 0xff, 0xa3, 0x00, 0xf0, 0x55, 0x00, 0xff, 0xd0, 0x05, 0x12, 0x00,
+        ])
+        .unwrap();
+        vm.run_until_halt(12);
+
+        let snapshot = vm.snapshot();
+        let mut expected = vm.clone();
+
+        expected.run_until_halt(30);
+        vm.run_until_halt(30);
+
+        let mut restored = VM::new();
+
+        assert_eq!(restored.restore(&snapshot), Ok(()));
+        assert_eq!(restored.display.resolution(), Resolution::Hires);
+        assert_eq!(restored.rom_hash(), vm.rom_hash());
+
+        restored.run_until_halt(30);
+
+        assert_eq!(restored.regs.v, expected.regs.v);
+        assert_eq!(restored.memory, expected.memory);
+        assert_eq!(restored.cycles(), expected.cycles());
+        assert_eq!(restored.display.to_ascii(), expected.display.to_ascii());
+
+        let mut invalid = snapshot.clone();
+        invalid.pixels.pop();
+
+        assert_eq!(restored.restore(&invalid), Err(VmError::InvalidSnapshot));
+
+        let mut invalid = snapshot.clone();
+        invalid.rom_start = usize::MAX;
+
+        assert_eq!(restored.restore(&invalid), Err(VmError::InvalidSnapshot));
+    }
+
+    #[test]
+    fn run_frame_default() {
+        let mut vm = VM::new();
+
+        vm.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 0x01 ; JP 0x200
+
+        assert_eq!(vm.cycles_per_frame, DEFAULT_CYCLES_PER_FRAME);
+
+        vm.run_frame_default();
+
+        assert_eq!(vm.cycles(), DEFAULT_CYCLES_PER_FRAME as u64);
+        assert_eq!(vm.regs.v[0], 6);
+
+        vm.cycles_per_frame = 30;
+        vm.reset_keeping_rom();
+        vm.regs.delay_timer = 5;
+        vm.run_frame_default();
+
+        assert_eq!(vm.cycles(), 30);
+        assert_eq!(vm.regs.v[0], 15);
+        assert_eq!(vm.regs.delay_timer, 4);
+    }
+
+    #[test]
+    fn opcode_0nnn() {
+        let mut vm = VM::new();
+
+        vm.process_opcode(0x0123); // SYS 0x123
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.status(), Status::Running);
+    }
+
+    #[test]
+    fn opcode_0nnn_strict() {
+        let mut vm = VM::new();
+
+        vm.strict_sys = true;
+        vm.write_u16(0x200, 0x0123); // SYS 0x123
+
+        let result = vm.step();
+
+        assert_eq!(
+            result.status,
+            StepStatus::Fault(VmError::UnsupportedSys(0x123))
+        );
+        assert_eq!(vm.fault(), Some(VmError::UnsupportedSys(0x123)));
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+    }
+
+    #[test]
+    fn opcode_coverage() {
+        let mut vm = VM::new();
+
+        vm.opcode_stats_enabled = true;
+        vm.load_rom_bytes(&[
+            0x60, 0x05, // LD V0, 0x05
+            0x61, 0x01, // LD V1, 0x01
+            0x80, 0x15, // SUB V0, V1
+            0x30, 0x00, // SE V0, 0x00
+            0x12, 0x04, // JP 0x204
+            0x81, 0x06, // SHR V1
+            0x12, 0x0c, // JP 0x20c
+        ])
+        .unwrap();
+
+        assert_eq!(vm.run_until_halt(100), RunResult::SelfJump);
+
+        let expected: BTreeSet<u16> = [0x6000, 0x8005, 0x3000, 0x1000, 0x8006]
+            .iter()
+            .copied()
+            .collect();
+
+        assert_eq!(vm.opcode_coverage(), &expected);
+
+        vm.reset_opcode_stats();
+
+        assert!(vm.opcode_coverage().is_empty());
+    }
+
+    #[test]
+    fn vx_masking() {
+        let mut vm = VM::new();
+
+        vm.set_vx(0x13, 0x42);
+
+        assert_eq!(vm.regs.v[0x3], 0x42);
+        assert_eq!(vm.vx(0x13), 0x42);
+        assert_eq!(vm.vx(0xf3), 0x42);
+
+        vm.set_vx(0xff, 0x01);
+
+        assert_eq!(vm.regs.v[0xf], 0x01);
+        assert_eq!(vm.regs.v.iter().filter(|&&v| v != 0).count(), 2);
+    }
+
+    #[test]
+    fn exxx_dispatch() {
+        let mut vm = VM::new();
+
+        vm.regs.v[1] = 0x3;
+        vm.keypad.key_down(0x3);
+        vm.process_opcode(0xe19e); // SKP V1
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+
+        vm.process_opcode(0xe1a1); // SKNP V1
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+
+        // Only the full low byte selects a handler, a matching low nibble is not enough
+        for opcode in [0xe10e, 0xe1ae, 0xe191, 0xe1e1] {
+            vm.write_u16(vm.regs.pc as usize, opcode);
+
+            assert_eq!(vm.peek_instruction(), Err(VmError::InvalidOpcode(opcode)));
+        }
+    }
+
+    #[test]
+    fn fxxx_dispatch_bcd() {
+        let mut vm = VM::new();
+
+        vm.regs.v[1] = 254;
+        vm.regs.i = 0x300;
+        vm.process_opcode(0xf133); // LD B, V1
+
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(&vm.memory[0x300..0x303], &[2, 5, 4]);
+    }
+
+    #[test]
+    fn fxxx_dispatch() {
+        // F015, F055 and F065 share the low nibble but each has its own handler
+        let mut vm = VM::new();
+
+        vm.regs.v[0] = 0x42;
+        vm.regs.i = 0x300;
+        vm.process_opcode(0xf015); // LD DT, V0
+
+        assert_eq!(vm.regs.delay_timer, 0x42);
+        assert_eq!(vm.memory[0x300], 0);
+
+        vm.process_opcode(0xf055); // LD [I], V0
+
+        assert_eq!(vm.memory[0x300], 0x42);
+        assert_eq!(vm.regs.delay_timer, 0x42);
+
+        vm.memory[0x300] = 0x17;
+        vm.process_opcode(0xf065); // LD V0, [I]
+
+        assert_eq!(vm.regs.v[0], 0x17);
+        assert_eq!(vm.memory[0x300], 0x17);
+        assert_eq!(vm.regs.delay_timer, 0x42);
+        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+
+        for (opcode, instruction) in [
+            (0xf015, Instruction::LdDtVx(0)),
+            (0xf055, Instruction::LdIVx(0)),
+            (0xf065, Instruction::LdVxI(0)),
+        ] {
+            assert_eq!(vm.decode(opcode), Ok(instruction));
+        }
+    }
+
+    #[test]
+    fn strict_alignment() {
+        let mut vm = VM::new();
+
+        vm.load_rom_bytes(&[0x12, 0x03, 0x00, 0x60, 0x42, 0x00])
+            .unwrap(); // JP 0x203 ; LD V0, 0x42
+        vm.step();
+
+        assert_eq!(vm.regs.pc, 0x203);
+        assert_eq!(vm.step().status, StepStatus::Ok);
+        assert_eq!(vm.regs.v[0], 0x42);
+
+        vm.reset_keeping_rom();
+        vm.strict_alignment = true;
+        vm.step();
+
+        let result = vm.step();
+
+        assert_eq!(
+            result.status,
+            StepStatus::Fault(VmError::MisalignedPc(0x203))
+        );
+        assert_eq!(result.pc, 0x203);
+        assert_eq!(vm.status(), Status::Fault(VmError::MisalignedPc(0x203)));
+        assert_eq!(vm.regs.v[0], 0);
+        assert_eq!(vm.cycles(), 1);
+    }
+
+    #[test]
+    fn on_event() {
+        use alloc::sync::Arc;
+        use std::sync::Mutex;
+
         let mut vm = VM::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+
+        vm.on_event(Box::new(move |event| log.lock().unwrap().push(event)));
+        vm.load_rom_bytes(&[
+            0x00, 0xe0, // CLS
+            0x60, 0x03, // LD V0, 0x03
+            0xf0, 0x29, // LD F, V0
+            0xd0, 0x05, // DRW V0, V0, 5
+            0xd0, 0x05, // DRW V0, V0, 5
+            0x22, 0x10, // CALL 0x210
+            0xf0, 0x18, // LD ST, V0
+            0x00, 0xfd, // EXIT
+            0x00, 0xee, // RET
+        ])
+        .unwrap();
+        vm.run_until_halt(20);
+
+        for _ in 0..3 {
+            vm.tick_timers();
+        }
 
-        let address: usize = 10;
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                VmEvent::Cls,
+                VmEvent::Drw {
+                    x: 3,
+                    y: 3,
+                    collision: false
+                },
+                VmEvent::Drw {
+                    x: 3,
+                    y: 3,
+                    collision: true
+                },
+                VmEvent::StackPush(0x20c),
+                VmEvent::StackPop(0x20c),
+                VmEvent::BeepStart,
+                VmEvent::Halt,
+                VmEvent::BeepStop,
+            ]
+        );
+
+        vm.clear_event_hook();
+        vm.reset_keeping_rom();
+        vm.run_until_halt(20);
+
+        assert_eq!(events.lock().unwrap().len(), 8);
+    }
 
-        vm.memory[address] = 0xaa;
+    #[test]
+    fn run_n() {
+        let rom = [
+            0x60, 0x00, // LD V0, 0x00
+            0xc1, 0x0f, // RND V1, 0x0f
+            0x80, 0x14, // ADD V0, V1
+            0xf1, 0x29, // LD F, V1
+            0xd0, 0x15, // DRW V0, V1, 5
+            0x40, 0xff, // SNE V0, 0xff
+            0x00, 0xfd, // EXIT
+            0x60, 0x00, // LD V0, 0x00
+            0x12, 0x02, // JP 0x202
+        ];
+
+        let mut stepped = VM::with_seed(9);
+        let mut batched = VM::with_seed(9);
+
+        stepped.load_rom_bytes(&rom).unwrap();
+        batched.load_rom_bytes(&rom).unwrap();
+        batched.set_decode_cache_enabled(true);
+        stepped.add_breakpoint(0x20c);
+        batched.add_breakpoint(0x20c);
+
+        for _ in 0..500 {
+            stepped.step();
+        }
 
-        let read = vm.read_u8(address);
+        assert_eq!(batched.run_n(500), 500);
+        assert_eq!(batched.regs.v, stepped.regs.v);
+        assert_eq!(batched.regs.pc, stepped.regs.pc);
+        assert_eq!(batched.cycles(), stepped.cycles());
+        assert_eq!(batched.display.to_ascii(), stepped.display.to_ascii());
 
-        assert_eq!(read, 0xaa);
+        // Stops at a breakpoint like step, the next call runs past it
+        batched.remove_breakpoint(0x20c);
+        batched.add_breakpoint(0x208);
+
+        let executed = batched.run_n(10);
+
+        assert_eq!(batched.regs.pc, 0x208);
+        assert!(executed < 10);
+        assert_eq!(batched.run_n(1), 1);
+        assert_eq!(batched.regs.pc, 0x20a);
+
+        batched.clear_breakpoints();
+        batched.write_u16(0x20a, 0x00fd); // EXIT
+
+        assert_eq!(batched.run_n(10), 1);
+        assert!(batched.is_halted());
+        assert_eq!(batched.run_n(10), 0);
     }
 
     #[test]
-    fn memory_write_u16() {
+    fn run_n_fast_path() {
+        let program = [
+            0xa216, // 0x200: LD I, 0x216
+            0x61fd, // 0x202: LD V1, 0xfd
+            0x7201, // 0x204: ADD V2, 0x01
+            0x3200, // 0x206: SE V2, 0x00
+            0x1204, // 0x208: JP 0x204
+            0x7301, // 0x20a: ADD V3, 0x01
+            0x3302, // 0x20c: SE V3, 0x02
+            0x1216, // 0x20e: JP 0x216
+            0xf155, // 0x210: LD [I], V1
+            0x1216, // 0x212: JP 0x216
+            0x0000, // 0x214
+            0x7401, // 0x216: ADD V4, 0x01
+            0x1204, // 0x218: JP 0x204
+        ];
+
+        let mut stepped = VM::new();
+        let mut batched = VM::new();
+
+        stepped
+            .write_program(INITIAL_PC as usize, &program)
+            .unwrap();
+        batched
+            .write_program(INITIAL_PC as usize, &program)
+            .unwrap();
+        batched.set_decode_cache_enabled(true);
+
+        while !stepped.is_halted() {
+            stepped.step();
+        }
+
+        // The second pass overwrites the cached ADD V4, 0x01 with EXIT
+        assert_eq!(batched.run_n(10_000), stepped.cycles() as usize);
+        assert!(batched.is_halted());
+        assert_eq!(batched.regs.v, stepped.regs.v);
+        assert_eq!(batched.regs.v[4], 1);
+        assert_eq!(batched.regs.pc, stepped.regs.pc);
+        assert_eq!(batched.run_n(10), 0);
+
+        // A fault stops it after the instructions before it
+        batched.reset();
+        batched
+            .write_program(INITIAL_PC as usize, &[0x7001, 0xffff])
+            .unwrap();
+
+        assert_eq!(batched.run_n(10), 1);
+        assert_eq!(batched.fault(), Some(VmError::InvalidOpcode(0xffff)));
+        assert_eq!(batched.run_n(10), 0);
+    }
+
+    #[test]
+    fn set_font() {
         let mut vm = VM::new();
+        let mut font = FONT;
 
-        let address: usize = 10;
+        // A 7 with a crossbar
+        font[7 * FONT_GLYPH_SIZE..8 * FONT_GLYPH_SIZE]
+            .copy_from_slice(&[0xf0, 0x10, 0x70, 0x20, 0x20]);
+        vm.set_font(&font);
+        vm.reset();
 
-        vm.write_u16(address, 0xaabb);
+        assert_eq!(&vm.memory[FONT_START..FONT_START + FONT.len()], &font[..]);
 
-        assert_eq!(vm.memory[address], 0xaa);
-        assert_eq!(vm.memory[address + 1], 0xbb);
+        vm.regs.v[0] = 7;
+        vm.process_opcode(0xf029); // LD F, V0
+
+        assert_eq!(vm.regs.i as usize, FONT_START + 7 * FONT_GLYPH_SIZE);
+
+        vm.regs.v[0] = 0;
+        vm.process_opcode(0xd005); // DRW V0, V0, 5
+
+        let rows: Vec<String> = vm
+            .display
+            .to_ascii_with('#', '.')
+            .lines()
+            .take(5)
+            .map(|line| String::from(&line[..4]))
+            .collect();
+
+        assert_eq!(rows, ["####", "...#", ".###", "..#.", "..#."]);
     }
 
     #[test]
-    fn memory_write_u8() {
+    fn run_frame_waiting_for_key() {
         let mut vm = VM::new();
 
-        let address: usize = 10;
+        vm.load_rom_bytes(&[0xf3, 0x0a, 0x73, 0x01]).unwrap(); // LD V3, K ; ADD V3, 0x01
+        vm.regs.delay_timer = 10;
+        vm.regs.sound_timer = 3;
 
-        vm.write_u8(address, 0xaa);
+        for frame in 1..=4 {
+            vm.run_frame(20);
 
-        assert_eq!(vm.memory[address], 0xaa);
+            assert_eq!(vm.regs.pc, INITIAL_PC);
+            assert_eq!(vm.cycles(), frame);
+            assert_eq!(vm.regs.delay_timer, 10 - frame as u8);
+        }
+
+        assert_eq!(vm.regs.sound_timer, 0);
+
+        vm.keypad.key_down(0x5);
+        vm.run_frame(2);
+
+        assert_eq!(vm.regs.v[3], 0x6);
+        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        assert_eq!(vm.regs.delay_timer, 5);
     }
 
     #[test]
-    fn opcode_00ee() {
+    fn protect_interpreter_area() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
+        vm.load_rom_bytes(&[0xf1, 0x55]).unwrap(); // LD [I], V1
+        vm.regs.v[0] = 0xaa;
+        vm.regs.v[1] = 0xbb;
+        vm.regs.i = FONT_START as u16;
+        vm.step();
 
-        let sp = vm.regs.sp - 1;
-        vm.regs.sp = sp;
-        vm.stack[vm.regs.sp as usize] = vm.regs.pc;
-        vm.regs.pc += 2;
+        assert_eq!(vm.status(), Status::Running);
+        assert_eq!(&vm.memory[FONT_START..FONT_START + 2], &[0xaa, 0xbb]);
 
-        vm.write_u16(vm.regs.pc as usize, 0x00ee); // RET
-        vm.step();
+        vm.reset_keeping_rom();
+        vm.protect_interpreter_area = true;
+        vm.regs.v[0] = 0xaa;
+        vm.regs.v[1] = 0xbb;
+        vm.regs.i = INITIAL_PC - 1;
 
+        let result = vm.step();
+
+        assert_eq!(
+            result.status,
+            StepStatus::Fault(VmError::WriteProtected(0x1ff))
+        );
         assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
-        assert_eq!(vm.stack[sp as usize], 0);
+        assert_eq!(vm.memory[0x1ff], 0);
+        assert_eq!(vm.memory[0x200], 0xf1);
+
+        // Writes at INITIAL_PC and above and host writes are allowed
+        vm.reset_keeping_rom();
+        vm.regs.i = 0x300;
+        vm.step();
+        vm.write_u8(FONT_START, 0x12);
+
+        assert_eq!(vm.status(), Status::Running);
+        assert_eq!(vm.memory[FONT_START], 0x12);
     }
 
     #[test]
-    fn opcode_1nnn() {
-        let mut vm = VM::new();
+    fn attach_bus() {
+        use alloc::sync::Arc;
+        use std::sync::Mutex;
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
+        struct MockBus {
+            memory: Vec<u8>,
+            log: Arc<Mutex<Vec<(AccessKind, usize, u8)>>>,
+        }
 
-        vm.write_u16(vm.regs.pc as usize, 0x1123); // JP 0x123
+        impl Bus for MockBus {
+            fn read(&self, address: usize) -> u8 {
+                let value = self.memory[address];
+                self.log
+                    .lock()
+                    .unwrap()
+                    .push((AccessKind::Read, address, value));
+                value
+            }
+
+            fn write(&mut self, address: usize, value: u8) {
+                self.memory[address] = value;
+                self.log
+                    .lock()
+                    .unwrap()
+                    .push((AccessKind::Write, address, value));
+            }
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut memory = vec![0; MEMORY_SIZE];
+
+        memory[0x200..0x204].copy_from_slice(&[0xa3, 0x00, 0xf0, 0x55]); // LD I, 0x300 ; LD [I], V0
+
+        let mut vm = VM::new();
+
+        vm.attach_bus(Box::new(MockBus {
+            memory,
+            log: log.clone(),
+        }));
+        vm.regs.v[0] = 0x42;
+        vm.step();
         vm.step();
 
-        assert_eq!(vm.regs.pc, 0x0123);
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                (AccessKind::Read, 0x200, 0xa3),
+                (AccessKind::Read, 0x201, 0x00),
+                (AccessKind::Read, 0x202, 0xf0),
+                (AccessKind::Read, 0x203, 0x55),
+                (AccessKind::Write, 0x300, 0x42),
+            ]
+        );
+        assert_eq!(vm.memory[0x300], 0);
+        assert_eq!(vm.read_u8(0x300), 0x42);
+
+        vm.detach_bus();
+
+        assert_eq!(vm.read_u8(0x300), 0);
     }
 
     #[test]
-    fn opcode_2nnn() {
+    fn beep_transitions() {
+        use alloc::sync::Arc;
+        use std::sync::Mutex;
+
         let mut vm = VM::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+        vm.on_event(Box::new(move |event| log.lock().unwrap().push(event)));
+        vm.regs.sound_timer = 2;
 
-        vm.write_u16(vm.regs.pc as usize, 0x2123); // CALL 0x123
-        vm.step();
+        assert_eq!(vm.tick_timers(), None);
+        assert!(events.lock().unwrap().is_empty());
+        assert_eq!(vm.tick_timers(), Some(VmEvent::BeepStop));
+        assert_eq!(*events.lock().unwrap(), [VmEvent::BeepStop]);
+        assert_eq!(vm.tick_timers(), None);
+        assert_eq!(events.lock().unwrap().len(), 1);
 
-        assert_eq!(vm.regs.pc, 0x0123);
-        assert_eq!(vm.regs.sp, (STACK_SIZE - 1) as u16);
-        assert_eq!(vm.stack[vm.regs.sp as usize], INITIAL_PC + 2);
+        vm.regs.v[0] = 1;
+        vm.process_opcode(0xf018); // LD ST, V0
+        vm.process_opcode(0xf018); // LD ST, V0
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [VmEvent::BeepStop, VmEvent::BeepStart]
+        );
+        assert_eq!(vm.tick_timers(), Some(VmEvent::BeepStop));
     }
 
     #[test]
-    fn opcode_3xkk() {
+    fn run_until_halt_cycle_limit() {
         let mut vm = VM::new();
 
+        // JP 0x202 ; JP 0x200, loops forever without ever jumping to itself
+        vm.load_rom_bytes(&[0x12, 0x02, 0x12, 0x00]).unwrap();
+
+        assert_eq!(vm.run_until_halt(1000), RunResult::CycleLimitReached);
+        assert_eq!(vm.cycles(), 1000);
+
+        // LD V0, K with no key pressed
+        vm.load_rom_bytes(&[0xf0, 0x0a]).unwrap();
+
+        assert_eq!(vm.run_until_halt(50), RunResult::CycleLimitReached);
+        assert_eq!(vm.cycles(), 50);
         assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.run_until_halt(0), RunResult::CycleLimitReached);
+        assert_eq!(vm.cycles(), 50);
+    }
 
-        vm.write_u16(vm.regs.pc as usize, 0x3123); // SE V1, 0x23
-        vm.step();
+    #[test]
+    fn run_frame_timed() {
+        let mut cheap = VM::new();
+        let mut costly = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        cheap.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 0x01 ; JP 0x200
+        costly
+            .load_rom_bytes(&[0x70, 0x01, 0xd1, 0x15, 0x12, 0x00])
+            .unwrap(); // ADD V0, 0x01 ; DRW V1, V1, 5 ; JP 0x200
 
-        vm.regs.v[1] = 0x23;
+        cheap.run_frame_timed(VIP_CYCLES_PER_FRAME);
+        costly.run_frame_timed(VIP_CYCLES_PER_FRAME);
 
-        vm.write_u16(vm.regs.pc as usize, 0x3123); // SE V1, 0x23
-        vm.step();
+        assert_eq!(cheap.cycles(), 223);
+        assert_eq!(costly.cycles(), 2);
+        assert_eq!(costly.regs.pc, INITIAL_PC + 4);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+        // The DRW went 1350 cycles over budget, which the next frame starts with
+        costly.run_frame_timed(VIP_CYCLES_PER_FRAME);
+
+        assert_eq!(costly.cycles(), 5);
+        assert_eq!(costly.regs.pc, INITIAL_PC + 4);
+
+        costly.run_frame_timed(1400);
+
+        assert_eq!(costly.cycles(), 5);
     }
 
     #[test]
-    fn opcode_4xkk() {
+    fn trace_mnemonics() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
+        vm.load_rom_bytes(&[0x61, 0x23, 0xf0, 0x00, 0x12, 0x34, 0x00, 0xfd])
+            .unwrap(); // LD V1, 0x23 ; LD I, long 0x1234 ; EXIT
 
-        vm.write_u16(vm.regs.pc as usize, 0x4123); // SNE V1, 0x23
-        vm.step();
+        assert_eq!(vm.step().mnemonic, None);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+        vm.trace_mnemonics = true;
 
-        vm.regs.v[1] = 0x23;
+        let result = vm.step();
 
-        vm.write_u16(vm.regs.pc as usize, 0x4123); // SNE V1, 0x23
-        vm.step();
+        assert_eq!(result.mnemonic.as_deref(), Some("LD I, long 0x1234"));
+        assert_eq!(result.instruction, Some(Instruction::LdILong));
+        assert_eq!(vm.step().mnemonic.as_deref(), Some("EXIT"));
+        assert_eq!(vm.step().mnemonic, None);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+        vm.reset_keeping_rom();
+
+        assert_eq!(vm.step().mnemonic.as_deref(), Some("LD V1, 0x23"));
     }
 
     #[test]
-    fn opcode_5xy0() {
+    fn opcode_dxyn_end_of_memory() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
+        vm.memory[MEMORY_SIZE - 2] = 0x80;
+        vm.memory[MEMORY_SIZE - 1] = 0x40;
+        vm.memory[0] = 0x20;
+        vm.memory[1] = 0x10;
+        vm.memory[2] = 0x08;
+        vm.regs.i = (MEMORY_SIZE - 2) as u16;
+        vm.process_opcode(0xd005); // DRW V0, V0, 5
+
+        // Rows 2-4 come from addresses 0x000-0x002
+        for row in 0..5 {
+            assert!(vm.display.pixel(row, row));
+            assert_eq!(vm.display.planes(row + 1, row), 0);
+        }
 
-        vm.write_u16(vm.regs.pc as usize, 0x5120); // SE V1, V2
-        vm.step();
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+    }
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
+    #[test]
+    fn clear_display() {
+        let mut vm = VM::new();
 
-        vm.regs.v[1] = 0x23;
+        vm.regs.v[0] = 0x12;
+        vm.regs.i = 0x50;
+        vm.regs.delay_timer = 7;
+        vm.process_opcode(0xd005); // DRW V0, V0, 5
+        vm.display.select_planes(2);
+        vm.display.set_pixel(0, 0, true);
 
-        vm.write_u16(vm.regs.pc as usize, 0x5120); // SE V1, V2
-        vm.step();
+        let memory = vm.memory.clone();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+        vm.clear_display();
+
+        assert_eq!(vm.display.to_ascii().matches('#').count(), 0);
+        assert_eq!(vm.display.planes(0, 0), 0);
+        assert_eq!(vm.display.selected_planes(), 2);
+        assert_eq!(vm.regs.v[0], 0x12);
+        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        assert_eq!(vm.regs.delay_timer, 7);
+        assert_eq!(vm.memory, memory);
     }
 
     #[test]
-    fn opcode_6xkk() {
+    fn clear_timers() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[1], 0x00);
-
-        vm.write_u16(vm.regs.pc as usize, 0x6123); // LD V1, 0x23
-        vm.step();
+        vm.regs.v[3] = 0x34;
+        vm.regs.i = 0x300;
+        vm.regs.delay_timer = 10;
+        vm.regs.sound_timer = 20;
+        vm.display.set_pixel(1, 1, true);
+        vm.clear_timers();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[1], 0x23);
+        assert_eq!(vm.regs.delay_timer, 0);
+        assert_eq!(vm.regs.sound_timer, 0);
+        assert!(!vm.is_buzzer_active());
+        assert_eq!(vm.regs.v[3], 0x34);
+        assert_eq!(vm.regs.i, 0x300);
+        assert!(vm.display.pixel(1, 1));
     }
 
     #[test]
-    fn opcode_7xkk() {
+    fn vf_aliasing() {
+        // With x == 0xF every flag setting opcode leaves the flag, not the result, in VF
+        let cases = [
+            (0x8f14, 0xf0, 0x20, 1), // ADD VF, V1
+            (0x8f14, 0x10, 0x20, 0), // ADD VF, V1
+            (0x8f15, 0x30, 0x20, 1), // SUB VF, V1
+            (0x8f15, 0x20, 0x20, 1), // SUB VF, V1
+            (0x8f15, 0x10, 0x20, 0), // SUB VF, V1
+            (0x8f16, 0x03, 0x00, 1), // SHR VF
+            (0x8f16, 0x02, 0x00, 0), // SHR VF
+            (0x8f17, 0x10, 0x20, 1), // SUBN VF, V1
+            (0x8f17, 0x30, 0x20, 0), // SUBN VF, V1
+            (0x8f1e, 0x81, 0x00, 1), // SHL VF
+            (0x8f1e, 0x41, 0x00, 0), // SHL VF
+        ];
+
+        for &(opcode, vf, v1, flag) in cases.iter() {
+            let mut vm = VM::new();
+
+            vm.regs.v[0xf] = vf;
+            vm.regs.v[1] = v1;
+            vm.process_opcode(opcode);
+
+            assert_eq!(vm.regs.v[0xf], flag, "{:#06x} with VF={:#04x}", opcode, vf);
+        }
+
+        // Vx == Vy with the flag register on the other side
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[1], 0x00);
+        vm.regs.v[1] = 0x05;
+        vm.regs.v[0xf] = 0x07;
+        vm.process_opcode(0x81f5); // SUB V1, VF
 
-        vm.regs.v[1] = 0x01;
-        vm.write_u16(vm.regs.pc as usize, 0x7123); // ADD V1, 0x23
-        vm.step();
+        assert_eq!(vm.regs.v[1], 0xfe);
+        assert_eq!(vm.regs.v[0xf], 0);
+    }
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[1], 0x24);
+    #[test]
+    fn load_at() {
+        let mut vm = VM::new();
+        let blob = [0xde, 0xad, 0xbe, 0xef];
+
+        vm.regs.v[0] = 0x12;
+
+        assert_eq!(vm.load_at(0x800, &blob), Ok(()));
+        assert_eq!(&vm.memory[0x800..0x804], &blob);
+        assert_eq!(vm.memory[0x7ff], 0);
+        assert_eq!(vm.memory[0x804], 0);
+        assert_eq!(vm.regs.v[0], 0x12);
+        assert_eq!(vm.load_at(MEMORY_SIZE - 4, &blob), Ok(()));
+        assert_eq!(
+            vm.load_at(MEMORY_SIZE - 3, &blob),
+            Err(VmError::OutOfBounds {
+                address: MEMORY_SIZE - 3,
+                size: 4
+            })
+        );
+        assert_eq!(vm.memory[MEMORY_SIZE - 3], 0xad);
+        assert!(vm.load_at(usize::MAX, &blob).is_err());
     }
 
     #[test]
-    fn opcode_8xy0() {
+    fn opcode_dxyn_plane_collision() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[1], 0x00);
-        assert_eq!(vm.regs.v[2], 0x00);
+        vm.memory[0x300..0x306].copy_from_slice(&[0x00, 0x80, 0x80, 0x00, 0x80, 0x80]);
+        vm.display.set_pixel(0, 0, true);
+        vm.process_opcode(0xf301); // PLANE 3
 
-        vm.regs.v[2] = 0x01;
-        vm.write_u16(vm.regs.pc as usize, 0x8120); // LD V1, V2
-        vm.step();
+        // Only the second plane is drawn to where just the first one is lit
+        vm.regs.i = 0x300;
+        vm.process_opcode(0xd001); // DRW V0, V0, 1
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[1], 0x01);
-        assert_eq!(vm.regs.v[2], 0x01);
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert_eq!(vm.display.planes(0, 0), 0b11);
+
+        // Erasing on the second plane only
+        vm.process_opcode(0xd001); // DRW V0, V0, 1
+
+        assert_eq!(vm.regs.v[0xf], 1);
+        assert_eq!(vm.display.planes(0, 0), 0b01);
+
+        // Erasing on the first plane only
+        vm.regs.i = 0x302;
+        vm.process_opcode(0xd001); // DRW V0, V0, 1
+
+        assert_eq!(vm.regs.v[0xf], 1);
+        assert_eq!(vm.display.planes(0, 0), 0b00);
+
+        // Lighting both
+        vm.regs.i = 0x304;
+        vm.process_opcode(0xd001); // DRW V0, V0, 1
+
+        assert_eq!(vm.regs.v[0xf], 0);
+        assert_eq!(vm.display.planes(0, 0), 0b11);
     }
 
     #[test]
-    fn opcode_8xy1() {
+    fn is_waiting_for_key() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[1], 0x00);
-        assert_eq!(vm.regs.v[2], 0x00);
+        vm.load_rom_bytes(&[0xf7, 0x0a, 0x12, 0x02]).unwrap(); // LD V7, K ; JP 0x202
+
+        assert_eq!(vm.is_waiting_for_key(), None);
 
-        vm.regs.v[1] = 0x12;
-        vm.regs.v[2] = 0x23;
-        vm.write_u16(vm.regs.pc as usize, 0x8121); // OR V1, V2
+        vm.step();
         vm.step();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[1], 0x33);
-        assert_eq!(vm.regs.v[2], 0x23);
+        assert_eq!(vm.is_waiting_for_key(), Some(7));
+
+        vm.keypad.key_down(0xc);
+        vm.step();
+
+        assert_eq!(vm.is_waiting_for_key(), None);
+        assert_eq!(vm.regs.v[7], 0xc);
+
+        vm.step();
+
+        assert_eq!(vm.is_waiting_for_key(), None);
     }
 
     #[test]
-    fn opcode_8xy2() {
+    fn invalid_opcode_faults() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[1], 0x00);
-        assert_eq!(vm.regs.v[2], 0x00);
+        vm.write_u16(0x200, 0xe1ff);
 
-        vm.regs.v[1] = 0x12;
-        vm.regs.v[2] = 0x23;
-        vm.write_u16(vm.regs.pc as usize, 0x8122); // AND V1, V2
-        vm.step();
+        let result = vm.step();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[1], 0x02);
-        assert_eq!(vm.regs.v[2], 0x23);
+        assert_eq!(
+            result.status,
+            StepStatus::Fault(VmError::InvalidOpcode(0xe1ff))
+        );
+        assert_eq!(vm.fault(), Some(VmError::InvalidOpcode(0xe1ff)));
+        assert_eq!(vm.regs.pc, 0x200);
+
+        vm.reset();
+        vm.process_opcode(0x8128);
+
+        assert_eq!(vm.fault(), Some(VmError::InvalidOpcode(0x8128)));
+        assert_eq!(vm.regs.pc, 0x200);
+        assert_eq!(vm.cycles(), 0);
     }
 
     #[test]
-    fn opcode_8xy3() {
+    fn wrapping_arithmetic() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[1], 0x00);
-        assert_eq!(vm.regs.v[2], 0x00);
+        vm.regs.v[1] = 0xf0;
+        vm.regs.i = 0xfff0;
+        vm.process_opcode(0x7120); // ADD V1, 0x20
+        vm.process_opcode(0xf11e); // ADD I, V1
 
-        vm.regs.v[1] = 0x12;
-        vm.regs.v[2] = 0x23;
-        vm.write_u16(vm.regs.pc as usize, 0x8123); // XOR V1, V2
+        assert_eq!(vm.regs.v[1], 0x10);
+        assert_eq!(vm.regs.i, 0x0000);
+    }
+
+    #[test]
+    fn random_opcode_streams() {
+        use rand::{RngCore, SeedableRng};
+        use rand_chacha::ChaCha8Rng;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0xda1e);
+
+        // Every combination of memory size, quirks, decode cache and strict_bounds, twice
+        for round in 0..48 {
+            let memory_size = [MEMORY_SIZE, 0x10000][round % 2];
+            let quirks = [Quirks::default(), Quirks::SUPER_CHIP, Quirks::XO_CHIP][round / 2 % 3];
+
+            let mut rom = vec![0; memory_size - INITIAL_PC as usize];
+            rng.fill_bytes(&mut rom);
+
+            let mut vm = VM::with_memory_size(memory_size);
+            vm.quirks = quirks;
+            vm.set_decode_cache_enabled(round / 6 % 2 == 1);
+            vm.strict_bounds = round / 12 % 2 == 1;
+            vm.regs.i = rng.next_u32() as u16;
+            vm.load_rom_bytes(&rom).unwrap();
+
+            for step in 0..2_000 {
+                let key = rng.next_u32() as u8 & 0x0f;
+
+                if rng.next_u32() & 1 == 1 {
+                    vm.keypad.key_down(key);
+                } else {
+                    vm.keypad.key_up(key);
+                }
+
+                vm.step();
+
+                if step as usize % DEFAULT_CYCLES_PER_FRAME == 0 {
+                    vm.tick_timers();
+                }
+
+                if vm.fault().is_some() || vm.halted {
+                    vm.reset_keeping_rom();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_nibble_not_sys() {
+        let mut vm = VM::new();
+        vm.strict_sys = true;
+
+        vm.write_u16(0x200, 0x00ff); // HIGH
+        vm.write_u16(0x202, 0x00c4); // SCD 4
+        vm.step();
+        vm.display.set_pixel(10, 0, true);
         vm.step();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[1], 0x31);
-        assert_eq!(vm.regs.v[2], 0x23);
+        assert_eq!(vm.fault(), None);
+        assert_eq!(vm.regs.pc, 0x204);
+        assert!(!vm.display.pixel(10, 0));
+        assert!(vm.display.pixel(10, 4));
+
+        for n in 0..16 {
+            assert_eq!(vm.decode(0x00c0 | n), Ok(Instruction::Scd(n as u8)));
+            assert_eq!(vm.decode(0x00d0 | n), Ok(Instruction::Scu(n as u8)));
+        }
     }
 
     #[test]
-    fn opcode_8xy4() {
+    fn run_until_draw() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0x1], 0);
-        assert_eq!(vm.regs.v[0x2], 0);
-        assert_eq!(vm.regs.v[0xf], 0);
+        let program = [
+            0x6005, // LD V0, 0x05
+            0x7003, // ADD V0, 0x03
+            0xf029, // LD F, V0
+            0xd115, // DRW V1, V1, 5
+            0x00e0, // CLS
+            0x120a, // JP 0x20a
+        ];
+        vm.write_program(0x200, &program).unwrap();
+
+        assert_eq!(vm.run_until_draw(100), RunResult::Drawn);
+        assert_eq!(vm.regs.pc, 0x208);
+        assert_eq!(vm.cycles(), 4);
+        assert!(vm.display.pixel(0, 0));
+
+        assert_eq!(vm.run_until_draw(100), RunResult::Drawn);
+        assert_eq!(vm.regs.pc, 0x20a);
+        assert!(!vm.display.pixel(0, 0));
+
+        assert_eq!(vm.run_until_draw(100), RunResult::SelfJump);
+    }
 
-        vm.regs.v[1] = 5;
-        vm.regs.v[2] = 5;
-        vm.write_u16(vm.regs.pc as usize, 0x8124); // ADD V1, V2
-        vm.step();
+    #[test]
+    fn set_palette() {
+        use crate::display::DEFAULT_PALETTE;
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0x1], 10);
-        assert_eq!(vm.regs.v[0x2], 5);
-        assert_eq!(vm.regs.v[0xf], 0);
+        let mut vm = VM::new();
+        let mut palette = DEFAULT_PALETTE;
+        palette[0b11] = [0x12, 0x34, 0x56, 0xff];
+
+        vm.set_palette(&palette);
+        vm.write_u16(0x200, 0xf301); // PLANE 3
+        vm.write_u16(0x202, 0xa050); // LD I, 0x050
+        vm.write_u16(0x204, 0xd001); // DRW V0, V0, 1
+        vm.run_until_draw(3);
+        vm.reset_keeping_rom();
+        vm.run_until_draw(3);
+
+        let rgba = vm.display.to_rgba(DEFAULT_PALETTE[1], DEFAULT_PALETTE[0]);
+
+        assert_eq!(vm.display.planes(0, 0), 0b11);
+        assert_eq!(rgba[0..4], [0x12, 0x34, 0x56, 0xff]);
+        assert_eq!(rgba[16..20], DEFAULT_PALETTE[0]);
+    }
 
-        vm.regs.v[1] = 255;
-        vm.regs.v[2] = 5;
-        vm.write_u16(vm.regs.pc as usize, 0x8124); // ADD V1, V2
+    #[test]
+    fn dump_core() {
+        let mut vm = VM::new();
+
+        vm.load_rom_bytes(&[
+            0x60, 0x0a, // LD V0, 0x0a
+            0xf0, 0x29, // LD F, V0
+            0xd1, 0x15, // DRW V1, V1, 5
+            0xf0, 0x18, // LD ST, V0
+            0x22, 0x0a, // CALL 0x20a
+            0x12, 0x0a, // JP 0x20a
+        ])
+        .unwrap();
+        vm.run_n(5);
+
+        let core = vm.dump_core();
+        let mut loaded = VM::new();
+
+        assert_eq!(loaded.load_core(&core), Ok(()));
+        assert_eq!(loaded.snapshot(), vm.snapshot());
+        assert!(loaded.snapshot().diff(&vm.snapshot()).is_empty());
+        assert_eq!(loaded.dump_core(), core);
+
+        loaded.step();
         vm.step();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
-        assert_eq!(vm.regs.v[0x1], 4);
-        assert_eq!(vm.regs.v[0x2], 5);
-        assert_eq!(vm.regs.v[0xf], 1);
+        assert_eq!(loaded.snapshot(), vm.snapshot());
+        assert_eq!(
+            loaded.load_core(&core[..core.len() - 1]),
+            Err(VmError::InvalidSnapshot)
+        );
+    }
+
+    #[test]
+    fn lores_scroll_half() {
+        for &(half, down, right, left) in &[(false, 4, 14, 6), (true, 2, 12, 8)] {
+            let mut vm = VM::new();
+            vm.quirks.lores_scroll_half = half;
+
+            vm.display.set_pixel(10, 0, true);
+            vm.process_opcode(0x00c4); // SCD 4
+
+            assert!(vm.display.pixel(10, down));
+            assert_eq!(vm.display.set_pixels().count(), 1);
+
+            vm.display.clear();
+            vm.display.set_pixel(10, 5, true);
+            vm.process_opcode(0x00fb); // SCR
+
+            assert!(vm.display.pixel(right, 5));
+            assert_eq!(vm.display.set_pixels().count(), 1);
+
+            vm.display.clear();
+            vm.display.set_pixel(10, 5, true);
+            vm.process_opcode(0x00fc); // SCL
+
+            assert!(vm.display.pixel(left, 5));
+            assert_eq!(vm.display.set_pixels().count(), 1);
+        }
     }
 
     #[test]
-    fn opcode_8xy5() {
+    fn is_valid_opcode() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0x1], 0);
-        assert_eq!(vm.regs.v[0x2], 0);
-        assert_eq!(vm.regs.v[0xf], 0);
-
-        vm.regs.v[1] = 5;
-        vm.regs.v[2] = 10;
-        vm.write_u16(vm.regs.pc as usize, 0x8125); // SUB V1, V2
-        vm.step();
-
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0x1], 251);
-        assert_eq!(vm.regs.v[0x2], 10);
-        assert_eq!(vm.regs.v[0xf], 0);
+        assert!(vm.is_valid_opcode(0x0123)); // SYS 0x123
+        assert!(vm.is_valid_opcode(0x00e0)); // CLS
+        assert!(!vm.is_valid_opcode(0xe1ff));
 
-        vm.regs.v[1] = 10;
-        vm.regs.v[2] = 5;
-        vm.write_u16(vm.regs.pc as usize, 0x8125); // SUB V1, V2
-        vm.step();
+        vm.strict_sys = true;
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
-        assert_eq!(vm.regs.v[0x1], 5);
-        assert_eq!(vm.regs.v[0x2], 5);
-        assert_eq!(vm.regs.v[0xf], 1);
+        assert!(!vm.is_valid_opcode(0x0123)); // SYS 0x123
+        assert!(vm.is_valid_opcode(0x00e0)); // CLS
     }
 
     #[test]
-    fn opcode_8xy6() {
-        let mut vm = VM::new();
+    fn with_stack_size() {
+        let mut vm = VM::new().with_stack_size(64).unwrap();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0x1], 0);
-        assert_eq!(vm.regs.v[0x2], 0);
-        assert_eq!(vm.regs.v[0xf], 0);
+        vm.write_u16(0x200, 0x2200); // CALL 0x200
 
-        vm.regs.v[1] = 8;
-        vm.write_u16(vm.regs.pc as usize, 0x8126); // SHR V1 {, V2}
-        vm.step();
+        for depth in 1..=64 {
+            vm.step();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0x1], 4);
-        assert_eq!(vm.regs.v[0xf], 0);
+            assert_eq!(vm.fault(), None);
+            assert_eq!(vm.regs.sp as usize, 64 - depth);
+        }
 
-        vm.regs.v[1] = 9;
-        vm.write_u16(vm.regs.pc as usize, 0x8126); // SHR V1 {, V2}
         vm.step();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
-        assert_eq!(vm.regs.v[0x1], 4);
-        assert_eq!(vm.regs.v[0xf], 1);
+        assert_eq!(vm.fault(), Some(VmError::StackOverflow));
+
+        vm.reset_keeping_rom();
+
+        assert_eq!(vm.stack, [0; 64]);
+        assert_eq!(vm.regs.sp, 64);
+
+        let snapshot = vm.snapshot();
+        let mut restored = VM::new();
+
+        assert_eq!(restored.restore(&snapshot), Ok(()));
+        assert_eq!(restored.stack.len(), 64);
     }
 
     #[test]
-    fn opcode_8xy7() {
+    fn with_stack_size_out_of_range() {
+        assert_eq!(
+            VM::new().with_stack_size(0).err(),
+            Some(VmError::InvalidStackSize(0))
+        );
+        assert_eq!(
+            VM::new().with_stack_size(0x10000).err(),
+            Some(VmError::InvalidStackSize(0x10000))
+        );
+
+        let vm = VM::with_memory_size(XO_MEMORY_SIZE)
+            .with_entry(0x1000)
+            .and_then(|vm| vm.with_stack_size(32))
+            .unwrap();
+
+        assert_eq!(vm.memory_size(), XO_MEMORY_SIZE);
+        assert_eq!(vm.regs.pc, 0x1000);
+        assert_eq!(vm.regs.sp, 32);
+    }
+
+    #[test]
+    fn advance() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0x1], 0);
-        assert_eq!(vm.regs.v[0x2], 0);
-        assert_eq!(vm.regs.v[0xf], 0);
+        vm.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 0x01 ; JP 0x200
+        vm.regs.delay_timer = 10;
 
-        vm.regs.v[1] = 8;
-        vm.regs.v[2] = 4;
-        vm.write_u16(vm.regs.pc as usize, 0x8127); // SUBN V1, V2
-        vm.step();
+        assert_eq!(vm.advance(Duration::from_secs(1) / 30), 2);
+        assert_eq!(vm.regs.delay_timer, 8);
+        assert_eq!(vm.cycles(), 2 * DEFAULT_CYCLES_PER_FRAME as u64);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0x1], 252);
-        assert_eq!(vm.regs.v[0xf], 0);
+        // Half a tick left over, then 0.3 ticks per call
+        assert_eq!(vm.advance(Duration::from_millis(5)), 0);
+        assert_eq!(vm.advance(Duration::from_millis(5)), 1);
+        assert_eq!(vm.advance(Duration::from_millis(5)), 0);
+        assert_eq!(vm.regs.delay_timer, 7);
 
-        vm.regs.v[1] = 4;
-        vm.regs.v[2] = 8;
-        vm.write_u16(vm.regs.pc as usize, 0x8127); // SUBN V1, V2
-        vm.step();
+        let frames: usize = (0..600)
+            .map(|_| vm.advance(Duration::from_secs(1) / 60))
+            .sum();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
-        assert_eq!(vm.regs.v[0x1], 4);
-        assert_eq!(vm.regs.v[0xf], 1);
+        assert_eq!(frames, 600);
+        assert_eq!(vm.regs.delay_timer, 0);
     }
 
     #[test]
-    fn opcode_8xye() {
+    fn power_on_and_soft_reset() {
+        // LD V0, 0x2a ; CALL 0x206 ; JP 0x204 ; LD ST, V0
+        let rom = [0x60, 0x2a, 0x22, 0x06, 0x12, 0x04, 0xf0, 0x18];
+        let mut font = FONT;
+        font[0] = 0x0f;
+
         let mut vm = VM::new();
+        vm.set_font(&font);
+        vm.load_rom_bytes(&rom).unwrap();
+        vm.run_n(3); // LD V0, 0x2a ; CALL 0x206 ; LD ST, V0
+        vm.display.set_pixel(1, 1, true);
+        vm.regs.delay_timer = 5;
+
+        vm.soft_reset();
 
+        assert_eq!(vm.memory[0x200..0x208], rom);
+        assert_eq!(vm.memory[FONT_START], 0x0f);
         assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0x1], 0);
-        assert_eq!(vm.regs.v[0x2], 0);
-        assert_eq!(vm.regs.v[0xf], 0);
+        assert_eq!(vm.regs.v, [0; V_REG_SIZE]);
+        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+        assert_eq!(vm.stack, [0; STACK_SIZE]);
+        assert_eq!((vm.regs.delay_timer, vm.regs.sound_timer), (0, 0));
+        assert!(!vm.display.pixel(1, 1));
 
-        vm.regs.v[1] = 8;
-        vm.write_u16(vm.regs.pc as usize, 0x812e); // SHL V1 {, V2}
-        vm.step();
+        vm.run_n(2);
+        vm.power_on_reset();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0x1], 16);
-        assert_eq!(vm.regs.v[0xf], 0);
+        assert!(vm.memory[0x200..0x208].iter().all(|&byte| byte == 0));
+        assert_eq!(vm.rom_hash(), VM::new().rom_hash());
+        assert_eq!(vm.memory[FONT_START], 0x0f);
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.regs.sp, STACK_SIZE as u16);
+    }
 
-        vm.regs.v[1] = 9;
-        vm.write_u16(vm.regs.pc as usize, 0x812e); // SHL V1 {, V2}
-        vm.step();
+    #[test]
+    fn opcode_00e0_planes() {
+        let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 4);
-        assert_eq!(vm.regs.v[0x1], 18);
-        assert_eq!(vm.regs.v[0xf], 1);
+        vm.load_rom_bytes(&[
+            0xf3, 0x01, // PLANE 3
+            0xa0, 0x50, // LD I, 0x050
+            0xd0, 0x05, // DRW V0, V0, 5
+            0xf2, 0x01, // PLANE 2
+            0x00, 0xe0, // CLS
+        ])
+        .unwrap();
+        vm.run_n(3);
+
+        // Plane 1 gets the 0 glyph, plane 2 the 1 glyph following it
+        assert_eq!(vm.display.planes(2, 0), 0b11);
+
+        vm.run_n(2);
+
+        assert_eq!(vm.display.planes(2, 0), 0b01);
+        assert!((0..8).all(|x| !vm.display.plane_pixel(1, x, 0)));
+        assert_eq!(vm.display.set_pixels().count(), 14);
+        assert_eq!(vm.display.selected_planes(), 0b10);
     }
 
     #[test]
-    fn opcode_9xy0() {
+    fn opcode_fx33_memory_end() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
+        vm.regs.v[1] = 123;
+        vm.regs.i = (MEMORY_SIZE - 1) as u16;
+        vm.process_opcode(0xf133); // LD B, V1
 
-        vm.write_u16(vm.regs.pc as usize, 0x9120); // SNE V1, V2
-        vm.step();
+        // Wraps around to the start of memory by default
+        assert_eq!(vm.memory[MEMORY_SIZE - 1], 1);
+        assert_eq!(vm.memory[0..2], [2, 3]);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
+        let mut vm = VM::new();
 
-        vm.regs.v[1] = 0x23;
+        vm.strict_bounds = true;
+        vm.regs.v[1] = 123;
+        vm.regs.i = (MEMORY_SIZE - 1) as u16;
+        vm.write_u16(0x200, 0xf133); // LD B, V1
+        vm.step();
 
-        vm.write_u16(vm.regs.pc as usize, 0x9120); // SNE V1, V2
+        assert_eq!(
+            vm.fault(),
+            Some(VmError::OutOfBounds {
+                address: MEMORY_SIZE - 1,
+                size: 3
+            })
+        );
+        assert_eq!(vm.regs.pc, 0x200);
+        assert_eq!(vm.memory[MEMORY_SIZE - 1], 0);
+        assert_eq!(vm.memory[0..2], [0, 0]);
+
+        vm.reset_keeping_rom();
+        vm.regs.v[1] = 123;
+        vm.regs.i = (MEMORY_SIZE - 3) as u16;
         vm.step();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 6);
+        assert_eq!(vm.fault(), None);
+        assert_eq!(vm.memory[MEMORY_SIZE - 3..], [1, 2, 3]);
     }
 
     #[test]
-    fn opcode_annn() {
+    fn fast_forward() {
+        use alloc::sync::Arc;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Mutex;
+
+        let rom = [
+            0x00, 0xe0, // CLS
+            0x60, 0x03, // LD V0, 0x03
+            0xf0, 0x29, // LD F, V0
+            0xd0, 0x05, // DRW V0, V0, 5
+            0x70, 0x01, // ADD V0, 0x01
+            0x22, 0x0e, // CALL 0x20e
+            0x12, 0x02, // JP 0x202
+            0x00, 0xee, // RET
+        ];
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+
+        let mut stepped = VM::new();
+        stepped.load_rom_bytes(&rom).unwrap();
+        stepped.trace_mnemonics = true;
+
+        for _ in 0..50 {
+            stepped.step();
+        }
+
+        let accesses = Arc::new(AtomicUsize::new(0));
+        let counter = accesses.clone();
+
         let mut vm = VM::new();
+        vm.load_rom_bytes(&rom).unwrap();
+        vm.on_event(Box::new(move |event| log.lock().unwrap().push(event)));
+        vm.set_memory_hook(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+
+        assert_eq!(vm.fast_forward(50), 50);
+        assert_eq!(vm.snapshot(), stepped.snapshot());
+        assert!(events.lock().unwrap().is_empty());
+        assert_eq!(accesses.load(Ordering::Relaxed), 0);
+
+        vm.run_until_draw(10);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [VmEvent::Drw {
+                x: 3,
+                y: 3,
+                collision: true
+            }]
+        );
+        assert!(accesses.load(Ordering::Relaxed) > 0);
+    }
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.i, 0);
+    #[test]
+    fn key_source() {
+        use alloc::sync::Arc;
+        use core::sync::atomic::{AtomicU16, Ordering};
 
-        vm.write_u16(vm.regs.pc as usize, 0xa123); // LD I, 0x123
-        vm.step();
+        struct MockKeys(Arc<AtomicU16>);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.i, 0x123);
+        impl KeySource for MockKeys {
+            fn is_key_down(&self, key: u8) -> bool {
+                self.0.load(Ordering::Relaxed) & (1 << key) != 0
+            }
+        }
+
+        let mut vm = VM::new();
+        let keys = Arc::new(AtomicU16::new(0));
+
+        vm.attach_key_source(Box::new(MockKeys(keys.clone())));
+        vm.load_rom_bytes(&[
+            0x60, 0x05, // LD V0, 0x05
+            0xe0, 0x9e, // SKP V0
+            0x12, 0x02, // JP 0x202
+            0x61, 0x01, // LD V1, 0x01
+            0x12, 0x08, // JP 0x208
+        ])
+        .unwrap();
+
+        vm.run_frame(10);
+
+        assert_eq!(vm.regs.v[1], 0x00);
+        assert!(!vm.keypad.is_pressed(5));
+
+        keys.store(1 << 5, Ordering::Relaxed);
+        vm.run_frame(10);
+
+        assert_eq!(vm.regs.v[1], 0x01);
+        assert!(vm.keypad.is_pressed(5));
+
+        keys.store(0, Ordering::Relaxed);
+        vm.detach_key_source();
+        vm.run_frame(1);
+
+        assert!(vm.keypad.is_pressed(5));
     }
 
     #[test]
-    fn opcode_bnnn() {
+    fn display_persistence() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0], 0);
+        vm.display.set_persistence(1);
+        vm.load_rom_bytes(&[
+            0xa0, 0x50, // LD I, 0x050
+            0xd0, 0x01, // DRW V0, V0, 1
+            0x00, 0xe0, // CLS
+        ])
+        .unwrap();
+        vm.run_n(3);
 
-        vm.regs.v[0] = 1;
+        assert!(!vm.display.pixel(0, 0));
+        assert_eq!(vm.display.age(0, 0), 1);
 
-        vm.write_u16(vm.regs.pc as usize, 0xb012); // JP V0, 0x12
-        vm.step();
+        vm.tick_timers();
 
-        assert_eq!(vm.regs.pc, 0x13);
-        assert_eq!(vm.regs.v[0], 1);
+        assert_eq!(vm.display.age(0, 0), 0);
     }
 
     #[test]
-    fn opcode_cxkk() {
+    fn data_guard() {
+        let rom = [
+            0x60, 0x01, // LD V0, 0x01
+            0x12, 0x06, // JP 0x206
+            0x60, 0x02, // data
+            0x12, 0x06, // JP 0x206
+        ];
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0], 0);
+        vm.load_rom_bytes(&rom).unwrap();
+        vm.enable_data_guard();
 
-        vm.write_u16(vm.regs.pc as usize, 0xc033); // RND V0, 0x33
-        vm.step();
+        assert_eq!(vm.run_until_halt(10), RunResult::SelfJump);
+        assert_eq!(vm.fault(), None);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0], 0x20);
+        vm.reset_keeping_rom();
+        vm.write_u16(0x202, 0x1204); // JP 0x204
+
+        assert_eq!(
+            vm.run_until_halt(10),
+            RunResult::Fault(VmError::ExecutingData(0x204))
+        );
+        assert_eq!(vm.regs.pc, 0x204);
+
+        vm.disable_data_guard();
+        vm.reset_keeping_rom();
+
+        assert_eq!(vm.run_until_halt(10), RunResult::SelfJump);
+        assert_eq!(vm.regs.v[0], 0x02);
     }
 
     #[test]
-    fn opcode_fx07() {
+    fn data_guard_restore() {
+        let mut big = VM::with_memory_size(0x10000);
+
+        big.load_at(0x2000, &[0x60, 0x01]).unwrap(); // LD V0, 0x01
+        big.set_entry(0x2000).unwrap();
+
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0], 0);
-        assert_eq!(vm.regs.delay_timer, 0);
+        vm.enable_data_guard();
 
-        vm.regs.delay_timer = 1;
-        vm.write_u16(vm.regs.pc as usize, 0xf007); // LD V0, DT
-        vm.step();
+        assert_eq!(vm.restore(&big.snapshot()), Ok(()));
+        assert_eq!(vm.step().status, StepStatus::Ok);
+        assert_eq!(vm.regs.v[0], 0x01);
+    }
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0], 1);
-        assert_eq!(vm.regs.delay_timer, 1);
+    #[test]
+    fn write_program() {
+        let mut vm = VM::new();
+        let program = [
+            0x6123, // LD V1, 0x23
+            0xa300, // LD I, 0x300
+            0x00e0, // CLS
+        ];
+
+        assert_eq!(vm.write_program(0x200, &program), Ok(()));
+        assert_eq!(
+            vm.memory[0x200..0x206],
+            [0x61, 0x23, 0xa3, 0x00, 0x00, 0xe0]
+        );
+
+        assert_eq!(
+            vm.write_program(MEMORY_SIZE - 4, &program),
+            Err(VmError::OutOfBounds {
+                address: MEMORY_SIZE - 4,
+                size: 6
+            })
+        );
+        assert!(vm.memory[MEMORY_SIZE - 4..].iter().all(|&byte| byte == 0));
     }
 
     #[test]
-    fn opcode_fx15() {
+    fn key_wait_timeout() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0], 0);
-        assert_eq!(vm.regs.delay_timer, 0);
+        vm.load_rom_bytes(&[0xf3, 0x0a, 0x12, 0x02]).unwrap(); // LD V3, K ; JP 0x202
+        vm.key_wait_timeout = Some(60);
 
-        vm.regs.v[0] = 1;
-        vm.write_u16(vm.regs.pc as usize, 0xf015); // LD DT, V0
-        vm.step();
+        for _ in 0..60 {
+            vm.run_frame(10);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0], 1);
-        assert_eq!(vm.regs.delay_timer, 1);
+            assert_eq!(vm.is_waiting_for_key(), Some(3));
+        }
+
+        vm.run_frame(10);
+
+        assert_eq!(vm.fault(), Some(VmError::KeyWaitTimeout));
+        assert_eq!(vm.regs.pc, INITIAL_PC);
+        assert_eq!(vm.cycles(), 61);
     }
 
     #[test]
-    fn opcode_fx18() {
+    fn key_wait_timeout_default_key() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0], 0);
-        assert_eq!(vm.regs.sound_timer, 0);
+        vm.load_rom_bytes(&[0xf3, 0x0a, 0x12, 0x02]).unwrap(); // LD V3, K ; JP 0x202
+        vm.key_wait_timeout = Some(2);
+        vm.key_wait_default = Some(0xa);
 
-        vm.regs.v[0] = 1;
-        vm.write_u16(vm.regs.pc as usize, 0xf018); // LD ST, V0
-        vm.step();
+        vm.run_frame(10);
+        vm.run_frame(10);
+
+        assert_eq!(vm.is_waiting_for_key(), Some(3));
+
+        vm.run_frame(10);
 
+        assert_eq!(vm.fault(), None);
+        assert_eq!(vm.regs.v[3], 0xa);
         assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.v[0], 1);
-        assert_eq!(vm.regs.sound_timer, 1);
     }
 
     #[test]
-    fn opcode_fx1e() {
+    fn key_wait_timeout_restarts() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.v[0], 0);
-        assert_eq!(vm.regs.i, 0);
+        vm.load_rom_bytes(&[0xf3, 0x0a, 0x12, 0x00]).unwrap(); // LD V3, K ; JP 0x200
+        vm.key_wait_timeout = Some(3);
 
-        vm.regs.i = 1;
-        vm.regs.v[0] = 1;
-        vm.write_u16(vm.regs.pc as usize, 0xf01e); // ADD I, V0
-        vm.step();
+        vm.run_frame(10);
+        vm.run_frame(10);
+        vm.keypad.key_down(0x1);
+        vm.run_frame(1);
+        vm.keypad.key_up(0x1);
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.i, 2);
-        assert_eq!(vm.regs.v[0], 1);
+        // The jump back is a fresh wait
+        for _ in 0..3 {
+            vm.run_frame(10);
+        }
+
+        assert_eq!(vm.fault(), None);
+        assert_eq!(vm.is_waiting_for_key(), Some(3));
+
+        vm.run_frame(10);
+
+        assert_eq!(vm.fault(), Some(VmError::KeyWaitTimeout));
     }
 
     #[test]
-    fn opcode_fx55() {
+    fn key_wait_forever_by_default() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.i, 0);
+        vm.load_rom_bytes(&[0xf3, 0x0a]).unwrap(); // LD V3, K
 
-        let i: usize = 10;
+        for _ in 0..1000 {
+            vm.run_frame(10);
+        }
 
-        vm.regs.i = i as u16;
-        vm.regs.v[0] = 1;
-        vm.regs.v[1] = 2;
-        vm.regs.v[2] = 3;
+        assert_eq!(vm.fault(), None);
+        assert_eq!(vm.is_waiting_for_key(), Some(3));
+    }
 
-        vm.write_u16(vm.regs.pc as usize, 0xf355); // LD [I], Vx
-        vm.step();
+    #[test]
+    fn memory_and_stack_views() {
+        let mut vm = VM::new();
+        let program = [
+            0x6142, // LD V1, 0x42
+            0xa300, // LD I, 0x300
+            0xf155, // LD [I], V1
+            0x2208, // CALL 0x208
+        ];
+        vm.write_program(INITIAL_PC as usize, &program).unwrap();
+        vm.run_n(4);
+
+        assert_eq!(vm.memory().len(), MEMORY_SIZE);
+        assert_eq!(vm.memory()[0x300..0x302], [0x00, 0x42]);
+        assert_eq!(vm.stack().len(), STACK_SIZE);
+        assert_eq!(vm.stack()[STACK_SIZE - 1], INITIAL_PC + 8);
+    }
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.i, i as u16);
-        assert_eq!(vm.regs.v[0], 1);
-        assert_eq!(vm.regs.v[1], 2);
-        assert_eq!(vm.regs.v[2], 3);
-        assert_eq!(vm.regs.v[3], 0);
+    #[test]
+    fn sprite_clip_bottom() {
+        for &(resolution, y) in &[(Resolution::Lores, 30), (Resolution::Hires, 62)] {
+            for &clip in &[false, true] {
+                let mut vm = VM::new();
+                vm.display.set_resolution(resolution);
+                vm.quirks.sprite_wrap = true;
+                vm.quirks.sprite_clip_bottom = clip;
+
+                vm.regs.v[0] = 10;
+                vm.regs.v[1] = y;
+                vm.regs.i = FONT_START as u16;
+                vm.process_opcode(0xd015); // DRW V0, V1, 5
+
+                // "0" is 0xf0, 0x90, 0x90, 0x90, 0xf0, rows 2 to 4 wrap to the top unless clipped
+                assert!(vm.display.pixel(10, y as usize));
+                assert!(vm.display.pixel(13, y as usize + 1));
+                assert_eq!(vm.display.pixel(10, 0), !clip);
+                assert_eq!(vm.display.pixel(13, 2), !clip);
+                assert_eq!(vm.display.set_pixels().count(), if clip { 6 } else { 14 });
+            }
+        }
 
-        assert_eq!(vm.memory[i], 1);
-        assert_eq!(vm.memory[i + 1], 2);
-        assert_eq!(vm.memory[i + 2], 3);
-        assert_eq!(vm.memory[i + 3], 0);
+        // The clip edge follows the mode switch, the bottom of lores is mid screen in hires
+        let mut vm = VM::new();
+        vm.quirks.sprite_wrap = true;
+        vm.quirks.sprite_clip_bottom = true;
+        vm.process_opcode(0x00ff); // HIGH
+
+        vm.regs.v[0] = 10;
+        vm.regs.v[1] = 30;
+        vm.regs.v[2] = 62;
+        vm.regs.i = FONT_START as u16;
+        vm.process_opcode(0xd015); // DRW V0, V1, 5
+        vm.process_opcode(0xd025); // DRW V0, V2, 5
+
+        assert!(vm.display.pixel(10, 34));
+        assert!(vm.display.pixel(10, 63));
+        assert!(!vm.display.pixel(10, 0));
+        assert_eq!(vm.display.set_pixels().count(), 14 + 6);
     }
 
     #[test]
-    fn opcode_fx65() {
+    fn key_wait_timeout_back_to_back() {
         let mut vm = VM::new();
 
-        assert_eq!(vm.regs.pc, INITIAL_PC);
-        assert_eq!(vm.regs.i, 0);
-        assert_eq!(vm.regs.v[0], 0);
-        assert_eq!(vm.regs.v[1], 0);
-        assert_eq!(vm.regs.v[2], 0);
-        assert_eq!(vm.regs.v[3], 0);
+        // LD V3, K ; LD V4, K
+        vm.load_rom_bytes(&[0xf3, 0x0a, 0xf4, 0x0a]).unwrap();
+        vm.key_wait_timeout = Some(3);
+        vm.key_wait_default = Some(0x7);
 
-        let i: usize = 10;
+        for _ in 0..4 {
+            vm.run_frame(10);
+        }
 
-        vm.regs.i = i as u16;
+        assert_eq!(vm.regs.v[3], 0x7);
+        assert_eq!(vm.is_waiting_for_key(), Some(4));
 
-        vm.memory[i] = 1;
-        vm.memory[i + 1] = 2;
-        vm.memory[i + 2] = 3;
-        vm.memory[i + 3] = 0;
+        for _ in 0..2 {
+            vm.run_frame(10);
+        }
 
-        vm.write_u16(vm.regs.pc as usize, 0xf365); // LD Vx, [I]
-        vm.step();
+        assert_eq!(vm.regs.v[4], 0);
+        assert_eq!(vm.is_waiting_for_key(), Some(4));
 
-        assert_eq!(vm.regs.pc, INITIAL_PC + 2);
-        assert_eq!(vm.regs.i, i as u16);
-        assert_eq!(vm.regs.v[0], 1);
-        assert_eq!(vm.regs.v[1], 2);
-        assert_eq!(vm.regs.v[2], 3);
-        assert_eq!(vm.regs.v[3], 0);
+        vm.run_frame(10);
 
-        assert_eq!(vm.memory[i], 1);
-        assert_eq!(vm.memory[i + 1], 2);
-        assert_eq!(vm.memory[i + 2], 3);
-        assert_eq!(vm.memory[i + 3], 0);
+        assert_eq!(vm.regs.v[4], 0x7);
+        assert_eq!(vm.fault(), None);
     }
 }
@@ -0,0 +1,98 @@
+use crate::vm::VM;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use wasm_bindgen::prelude::*;
+
+const ON_COLOR: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+const OFF_COLOR: [u8; 4] = [0x00, 0x00, 0x00, 0xff];
+
+// Browser facing wrapper over VM, the core stays free of wasm_bindgen types
+#[wasm_bindgen]
+pub struct WasmVm {
+    vm: VM,
+}
+
+impl Default for WasmVm {
+    fn default() -> Self {
+        WasmVm::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmVm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmVm {
+        WasmVm { vm: VM::new() }
+    }
+
+    // Rejected ROMs throw with the VmError message
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        self.vm
+            .load_rom_bytes(rom)
+            .map_err(|error| JsValue::from_str(&error.to_string()))
+    }
+
+    pub fn run_frame(&mut self, cycles: usize) {
+        self.vm.run_frame(cycles);
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.vm.keypad.key_down(key);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.vm.keypad.key_up(key);
+    }
+
+    pub fn width(&self) -> usize {
+        self.vm.display.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.vm.display.height()
+    }
+
+    // RGBA bytes, row by row, ready for an ImageData
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.vm.display.to_rgba(ON_COLOR, OFF_COLOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_rom_round_trip() {
+        let mut vm = WasmVm::new();
+        let rom = [
+            0xa0, 0x50, // LD I, 0x050
+            0xe0, 0xa1, // SKNP V0
+            0xd0, 0x05, // DRW V0, V0, 5
+            0x12, 0x06, // JP 0x206
+        ];
+
+        vm.load_rom(&rom).unwrap();
+
+        assert_eq!(&vm.vm.memory[0x200..0x208], &rom);
+
+        vm.run_frame(4);
+
+        assert_eq!(vm.framebuffer(), vm.vm.display.to_rgba(ON_COLOR, OFF_COLOR));
+        assert!(vm.framebuffer().chunks(4).all(|pixel| pixel == OFF_COLOR));
+
+        vm.load_rom(&rom).unwrap();
+        vm.key_down(0);
+        vm.run_frame(4);
+
+        let framebuffer = vm.framebuffer();
+
+        assert_eq!(framebuffer.len(), vm.width() * vm.height() * 4);
+        assert_eq!(&framebuffer[..16], &[ON_COLOR; 4].concat()[..]);
+        assert_eq!(&framebuffer[16..20], &OFF_COLOR);
+
+        vm.key_up(0);
+
+        assert!(!vm.vm.keypad.is_pressed(0));
+    }
+}
@@ -0,0 +1,56 @@
+// Own test binary, the logger and its max level are global to the process
+use dale::vm::VM;
+use std::sync::Mutex;
+
+static LOG_RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct CapturingLogger;
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOG_RECORDS
+            .lock()
+            .unwrap()
+            .push(format!("{}", record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger;
+
+#[test]
+fn opcode_logging() {
+    log::set_logger(&LOGGER).unwrap();
+
+    let mut vm = VM::new();
+
+    vm.write_u16(vm.regs.pc as usize, 0x6123); // LD V1, 0x23
+    vm.step();
+
+    assert!(LOG_RECORDS.lock().unwrap().is_empty());
+
+    log::set_max_level(log::LevelFilter::Trace);
+
+    vm.write_u16(vm.regs.pc as usize, 0x6123); // LD V1, 0x23
+    vm.step();
+
+    // fast_forward skips the log
+    vm.write_u16(vm.regs.pc as usize, 0x6e5a); // LD VE, 0x5a
+    vm.fast_forward(1);
+
+    log::set_max_level(log::LevelFilter::Off);
+
+    let records = LOG_RECORDS.lock().unwrap();
+
+    assert!(records
+        .iter()
+        .any(|record| record == "opcode: 0x6123 op: 0x06"));
+    assert!(!records
+        .iter()
+        .any(|record| record.starts_with("opcode: 0x6e5a")));
+}